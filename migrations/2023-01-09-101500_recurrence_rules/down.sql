@@ -0,0 +1,18 @@
+-- This file should undo anything in `up.sql`
+CREATE TYPE recurrence_type AS ENUM ('weekly', 'once');
+
+ALTER TABLE events ADD COLUMN recurrence recurrence_type NOT NULL DEFAULT 'once';
+
+-- Only weekly events can be represented, so the others are turned into one-off events
+UPDATE events SET recurrence = 'weekly' WHERE recurrence_frequency = 'weekly';
+
+ALTER TABLE events
+  ALTER COLUMN recurrence DROP DEFAULT,
+  DROP COLUMN recurrence_frequency,
+  DROP COLUMN recurrence_interval,
+  DROP COLUMN recurrence_by_day,
+  DROP COLUMN recurrence_until,
+  DROP COLUMN recurrence_count;
+
+DROP TYPE recurrence_frequency;
+DROP TYPE weekday;
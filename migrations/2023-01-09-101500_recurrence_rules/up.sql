@@ -0,0 +1,20 @@
+-- Your SQL goes here
+CREATE TYPE recurrence_frequency AS ENUM ('once', 'daily', 'weekly', 'monthly', 'yearly');
+CREATE TYPE weekday AS ENUM ('monday', 'tuesday', 'wednesday', 'thursday', 'friday', 'saturday', 'sunday');
+
+-- Replace the recurrence type with the parts of a recurrence rule, as in RFC 5545
+ALTER TABLE events
+  ADD COLUMN recurrence_frequency recurrence_frequency NOT NULL DEFAULT 'once',
+  ADD COLUMN recurrence_interval INT NOT NULL DEFAULT 1, -- e.g. 2 for events every other week
+  ADD COLUMN recurrence_by_day weekday[] NOT NULL DEFAULT '{}', -- the days of the week the event happens on
+  ADD COLUMN recurrence_until INT, -- a UNIX timestamp, in seconds, after which the event does not happen
+  ADD COLUMN recurrence_count INT; -- the number of times the event happens
+
+-- Keep the recurrence of the existing events
+UPDATE events SET recurrence_frequency = recurrence::text::recurrence_frequency;
+
+ALTER TABLE events
+  ALTER COLUMN recurrence_frequency DROP DEFAULT,
+  DROP COLUMN recurrence;
+
+DROP TYPE recurrence_type;
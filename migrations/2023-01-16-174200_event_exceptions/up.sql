@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE event_exceptions (
+  id SERIAL PRIMARY KEY,
+  event_id INT NOT NULL,
+  original_start_time INT NOT NULL, -- a UNIX timestamp, in seconds, of the occurrence that is changed
+  is_cancelled BOOLEAN NOT NULL,
+  new_start_time INT, -- a UNIX timestamp, in seconds, or NULL to keep the original time
+  new_duration INT, -- in seconds, or NULL to keep the duration of the event
+  FOREIGN KEY(event_id) REFERENCES events(id),
+
+  CONSTRAINT unique_exceptions UNIQUE (event_id, original_start_time)
+)
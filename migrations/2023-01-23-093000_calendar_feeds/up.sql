@@ -0,0 +1,7 @@
+-- Your SQL goes here
+CREATE TABLE calendar_feeds (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL UNIQUE,
+  token VARCHAR(64) NOT NULL UNIQUE, -- a random secret that is part of the URL of the feed
+  FOREIGN KEY(user_id) REFERENCES users(id)
+)
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- The IANA time zone the event repeats in, e.g. Europe/London. The existing events repeated in UTC
+ALTER TABLE events ADD COLUMN time_zone VARCHAR(64) NOT NULL DEFAULT 'UTC';
+
+ALTER TABLE events ALTER COLUMN time_zone DROP DEFAULT;
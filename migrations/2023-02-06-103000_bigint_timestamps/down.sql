@@ -0,0 +1,10 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE event_exceptions
+    ALTER COLUMN original_start_time TYPE INT,
+    ALTER COLUMN new_start_time TYPE INT,
+    ALTER COLUMN new_duration TYPE INT;
+
+ALTER TABLE events
+    ALTER COLUMN start_time TYPE INT,
+    ALTER COLUMN duration TYPE INT,
+    ALTER COLUMN recurrence_until TYPE INT;
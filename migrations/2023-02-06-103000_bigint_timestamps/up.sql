@@ -0,0 +1,10 @@
+-- Your SQL goes here
+ALTER TABLE events
+    ALTER COLUMN start_time TYPE BIGINT,
+    ALTER COLUMN duration TYPE BIGINT,
+    ALTER COLUMN recurrence_until TYPE BIGINT;
+
+ALTER TABLE event_exceptions
+    ALTER COLUMN original_start_time TYPE BIGINT,
+    ALTER COLUMN new_start_time TYPE BIGINT,
+    ALTER COLUMN new_duration TYPE BIGINT;
@@ -0,0 +1,2 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE friendships DROP CONSTRAINT unique_friendships;
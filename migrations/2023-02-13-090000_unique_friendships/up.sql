@@ -0,0 +1,9 @@
+-- Your SQL goes here
+-- Remove the duplicate friendships, keeping the oldest ones
+DELETE FROM friendships duplicate
+USING friendships original
+WHERE duplicate.owner_id = original.owner_id
+  AND duplicate.friend_id = original.friend_id
+  AND duplicate.id > original.id;
+
+ALTER TABLE friendships ADD CONSTRAINT unique_friendships UNIQUE (owner_id, friend_id);
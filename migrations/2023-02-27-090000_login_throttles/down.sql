@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE account_lockouts;
+DROP TABLE login_throttles;
+DROP TYPE throttle_kind;
@@ -0,0 +1,24 @@
+-- Your SQL goes here
+CREATE TYPE throttle_kind AS ENUM ('ip', 'username');
+
+-- The recent failed logins from an IP address or for a username
+CREATE TABLE login_throttles (
+  id SERIAL PRIMARY KEY,
+  kind throttle_kind NOT NULL,
+  subject VARCHAR(100) NOT NULL, -- the IP address or the username
+  failures INT NOT NULL,
+  last_failure_at BIGINT NOT NULL, -- a UNIX timestamp, in seconds
+  blocked_until BIGINT, -- a UNIX timestamp, in seconds, or NULL if logging in is allowed
+
+  CONSTRAINT unique_throttles UNIQUE (kind, subject)
+);
+
+-- An audit log of the accounts that were locked after too many failed logins
+CREATE TABLE account_lockouts (
+  id SERIAL PRIMARY KEY,
+  username VARCHAR(50) NOT NULL,
+  ip VARCHAR(100) NOT NULL, -- the address the last failed login came from
+  failures INT NOT NULL,
+  locked_at BIGINT NOT NULL,
+  locked_until BIGINT NOT NULL
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE recovery_codes;
+DROP TABLE two_factor_secrets;
@@ -0,0 +1,18 @@
+-- Your SQL goes here
+CREATE TABLE two_factor_secrets (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL UNIQUE,
+  secret BYTEA NOT NULL, -- the key shared with the authenticator app of the user
+  is_enabled BOOLEAN NOT NULL, -- false until the user has confirmed a code from the app
+  last_used_step BIGINT, -- the time step of the last code that was accepted, so that it can not be reused
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE TABLE recovery_codes (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL,
+  code_hash TEXT NOT NULL,
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX recovery_codes_user_id_index ON recovery_codes (user_id);
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+CREATE TYPE token_scope AS ENUM ('read_calendar', 'manage_groups', 'manage_events');
+
+-- Personal tokens that scripts can use the API with instead of a session cookie
+CREATE TABLE api_tokens (
+  id SERIAL PRIMARY KEY,
+  user_id INT NOT NULL,
+  name VARCHAR(100) NOT NULL, -- so that the user can tell their tokens apart
+  token_hash VARCHAR(64) NOT NULL UNIQUE, -- the SHA-256 hash of the token, as hex
+  scopes token_scope[] NOT NULL,
+  created_at BIGINT NOT NULL,
+  last_used_at BIGINT, -- a UNIX timestamp, in seconds, or NULL if it has not been used yet
+  FOREIGN KEY(user_id) REFERENCES users(id) ON DELETE CASCADE
+);
+
+CREATE INDEX api_tokens_user_id_index ON api_tokens (user_id);
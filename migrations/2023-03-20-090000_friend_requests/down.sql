@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX unique_friendship_pairs;
+
+-- Friendships were one-way, so an accepted one needs a row for each direction
+INSERT INTO friendships (owner_id, friend_id)
+SELECT friend_id, owner_id FROM friendships WHERE status = 'accepted';
+
+ALTER TABLE friendships DROP COLUMN status;
+DROP TYPE friendship_status;
@@ -0,0 +1,23 @@
+-- Your SQL goes here
+CREATE TYPE friendship_status AS ENUM ('pending', 'accepted');
+
+-- A friendship starts as a request from the owner that the friend has to accept
+ALTER TABLE friendships ADD COLUMN status friendship_status NOT NULL DEFAULT 'pending';
+
+-- Users who had added each other are friends already, and only need one of the two rows
+UPDATE friendships original
+SET status = 'accepted'
+FROM friendships reverse
+WHERE reverse.owner_id = original.friend_id
+  AND reverse.friend_id = original.owner_id
+  AND original.id < reverse.id;
+
+DELETE FROM friendships duplicate
+USING friendships original
+WHERE original.owner_id = duplicate.friend_id
+  AND original.friend_id = duplicate.owner_id
+  AND original.id < duplicate.id;
+
+-- There can only be one friendship or request between 2 users, whoever sent it
+CREATE UNIQUE INDEX unique_friendship_pairs
+ON friendships (LEAST(owner_id, friend_id), GREATEST(owner_id, friend_id));
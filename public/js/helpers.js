@@ -1,4 +1,10 @@
 
+// Get the token that was put in the page by the server, see `check_csrf_token` in the server
+function csrf_token() {
+  const meta = document.querySelector('meta[name="csrf-token"]');
+  return meta ? meta.content : "";
+}
+
 // A fetch helper. `f` is short for `fetch`
 function f(url, method, data) {
   const body = method === "GET" ? undefined : JSON.stringify(data);
@@ -8,9 +14,8 @@ function f(url, method, data) {
     headers: {
       "content-type": "application/json",
       accept: "application/json",
-    },
-    headers: {
-      'Content-Type': 'application/json',
+      // The server rejects requests that can change something without the token of the page
+      "x-csrf-token": csrf_token(),
     },
     body
   });
@@ -45,6 +50,15 @@ function to_ordinal(number) {
   return str + suffix;
 }
 
+/// Read the message of an error response, so that it can be shown to the user
+async function error_message(res) {
+  try {
+    return (await res.json()).message;
+  } catch {
+    return "Something went wrong. Please try again later.";
+  }
+}
+
 /// Request an endpoint and call the setter with the data returned
 async function request(endpoint, setter) {
   const res = await f(endpoint, "GET");
@@ -23,7 +23,7 @@ function Header() {
     // if it was not successful, show the error message
     if (res.status >= 400) {
       // Read the error message
-      const error = await res.text();
+      const error = await error_message(res);
       return console.error(error);
     }
 
@@ -34,10 +34,46 @@ function Header() {
     request("/api/get_notifications", set_notifications);
   }
 
+  /** a function to accept or decline a friend request
+  */
+  async function reply_to_friend_request(user_id, was_accepted) {
+    const endpoint = was_accepted ? "/api/accept_friend_request" : "/api/decline_friend_request";
+    const res = await f(endpoint, "POST", { user_id });
+
+    // if it was not successful, show the error message
+    if (res.status >= 400) {
+      // Read the error message
+      const error = await error_message(res);
+      return console.error(error);
+    }
+
+    // Refresh the page if the request has been accepted to use the updated list of friends
+    if (was_accepted) return window.location.reload();
+
+    // otherwise, refresh the notifications
+    request("/api/get_notifications", set_notifications);
+  }
+
+  /** a function to end the session and go back to the login page
+  */
+  async function logout() {
+    const res = await f("/api/logout", "POST", {});
+
+    // if it was not successful, show the error message
+    if (res.status >= 400) {
+      // Read the error message
+      const error = await error_message(res);
+      return console.error(error);
+    }
+
+    window.location.href = "/login";
+  }
+
   // A list of buttons to show only when the user is logged in
   const buttons_shown_when_logged_in = [
     <HeaderButton is_filled={true} content="Calendar" link="/" key={0} />,
     <HeaderButton content="Friends" link="/friends" key={1} />,
+    <HeaderButton content="Log out" on_click={logout} key={2} />,
   ];
   // A list of buttons to show only when the user is logged out
   const buttons_shown_when_logged_out = [
@@ -74,11 +110,13 @@ function Header() {
   </div >;
 
   function HeaderButton(props) {
-    const { content, link, is_filled } = props;
-    return <div className="col-6">
+    const { content, link, is_filled, on_click } = props;
+    return <div className="col">
       {/* Select between filled and not */}
       <Button variant={`${is_filled ? "" : "outline-"}primary`} onClick={() => {
-        // Redirect to the page
+        // Run the action if there is one
+        if (on_click) return on_click();
+        // Otherwise, redirect to the page
         window.location.href = link;
       }}
         className="mx-auto d-block w-100"
@@ -160,17 +198,26 @@ function Header() {
       {
         notifications.map((notification, i) => {
           // Get the data out of a notification
-          let { id, name } = notification.Invitation;
+          let text, reply;
+          if (notification.FriendRequest) {
+            const { id, username } = notification.FriendRequest;
+            text = `${username} wants to be your friend`;
+            reply = was_accepted => reply_to_friend_request(id, was_accepted);
+          } else {
+            const { id, name } = notification.Invitation;
+            text = `You have been invited to the group "${name}"`;
+            reply = was_accepted => reply_to_group_invitation(id, was_accepted);
+          }
 
           const notification_element = <div style={{
             width: "40vw",
             minWidth: "250px"
           }}>
-            <span>You have been invited to the group "{name}"</span>
+            <span>{text}</span>
             <div className="container-fluid mt-2">
               <div className="row justify-content-around">
-                <Button variant="success" className="col-5" onClick={() => reply_to_group_invitation(id, true)}>Accept</Button>
-                <Button variant="danger" className="col-5" onClick={() => reply_to_group_invitation(id, false)}>Reject</Button>
+                <Button variant="success" className="col-5" onClick={() => reply(true)}>Accept</Button>
+                <Button variant="danger" className="col-5" onClick={() => reply(false)}>Reject</Button>
               </div>
 
             </div>
@@ -3,8 +3,8 @@ const useRef = React.useRef;
 /** A component that displays a timetable
  */
 function Timetable(props) {
-  // date is any date in this week
-  const { events: raw_events, start_of_week_date, start_of_next_week_date } = props;
+  // The events are the occurrences within the week, as recurring events are expanded by the server
+  const { events: raw_events, start_of_week_date } = props;
 
   // If an event runs over to the next day, split it into two.
   let events = [];
@@ -76,21 +76,9 @@ function Timetable(props) {
 
               {
                 /* Filter out only the events from this day */
-                events.filter(el => {
-                  // Check if it is a weekly event
-                  if (el.recurrence_type === "Weekly") {
-                    // check if the day of the week is the same
-                    const is_same_day_of_the_week = el.start_time.day() === day_in_this_column.day()
-
-                    // Check if it is not before the first time the event has occured
-                    const is_at_least_first_time = el.start_time.unix() < start_of_next_week_date.unix();
-
-                    return is_same_day_of_the_week && is_at_least_first_time;
-                  } else {
-                    // Check that the day is the same by checking that the "beginning of the day" refers to the same day
-                    return el.start_time.startOf("day").unix() === day_in_this_column.startOf("day").unix();
-                  }
-                }
+                events.filter(el =>
+                  // Check that the day is the same by checking that the "beginning of the day" refers to the same day
+                  el.start_time.startOf("day").unix() === day_in_this_column.startOf("day").unix()
                 ).map((event, j) =>
                   <TimetableEvent key={j} event={event} container_ref={div_timetable_column_refs[i]} />
                 )
@@ -7,17 +7,21 @@ function Calendar() {
   const start_of_next_week_date = start_of_week_date.add(1, "w");
 
   const [events, set_events] = useState([]);
+  // The secret URL other calendar apps can subscribe to
+  const [feed_url, set_feed_url] = useState("");
   // em is short for "error message"
   const [overall_em, set_overall_em] = useState("");
 
-  // Load the events when the page is loaded, once
+  // Load the events happening in the week shown whenever the week changes
   useEffect(() => {
     async function get_data() {
-      const res = await f("/api/get_events", "GET");
+      const from = start_of_week_date.unix();
+      const to = start_of_next_week_date.unix();
+      const res = await f(`/api/get_events?from=${from}&to=${to}`, "GET");
       // If there was an error, display it
       if (res.status >= 400) {
         // Read the error message
-        const error = await res.text();
+        const error = await error_message(res);
         return set_overall_em(error);
       }
       // Parse the response
@@ -25,7 +29,7 @@ function Calendar() {
       set_events(events);
     }
     get_data();
-  }, [])
+  }, [start_of_week_date])
 
   return <PageContainerBoxLarge title="Your Calendar">
     <ErrorMessage em={overall_em} />
@@ -44,7 +48,7 @@ function Calendar() {
         }} />
       </div>
     </Pagination>
-    <Timetable events={events} start_of_week_date={start_of_week_date} start_of_next_week_date={start_of_next_week_date} />
+    <Timetable events={events} start_of_week_date={start_of_week_date} />
     <Button variant="primary" onClick={() => {
       // Redirect to the event creation page
       window.location.href = "/create_event";
@@ -53,5 +57,14 @@ function Calendar() {
     >
       New event
     </Button>
+    {/* Let the user subscribe to the calendar from other calendar apps */}
+    {feed_url === ""
+      ? <Button variant="link" onClick={() => request("/api/get_calendar_feed_url", data => set_feed_url(data.url))}
+        className="mt-2 mx-auto d-block"
+      >
+        Subscribe from another calendar app
+      </Button>
+      : <input type="text" readOnly className="form-control mt-2" value={feed_url} onFocus={e => e.target.select()} />
+    }
   </PageContainerBoxLarge>;
 }
@@ -17,7 +17,13 @@ function CreateEvent() {
 
   // Have variables to keep track of the state
   const [title, set_title] = useState("");
-  const [recurrence, set_recurrence] = useState("Weekly");
+  const [frequency, set_frequency] = useState("Weekly");
+  const [interval, set_interval] = useState(1);
+  const [by_day, set_by_day] = useState([]);
+  // How the recurrence ends: "Never", "Until" a date or after a "Count" of occurrences
+  const [recurrence_end, set_recurrence_end] = useState("Never");
+  const [until, set_until] = useState(time_now);
+  const [count, set_count] = useState(1);
   const [visibility, set_visibility] = useState("Private");
   const [group_id, set_group_id] = useState(-1); // represents an invalid group id
   const [start_time, set_start_time] = useState(time_now);
@@ -26,7 +32,11 @@ function CreateEvent() {
   // em is short for "error message"
   const [title_em, set_title_em] = useState("");
   const [time_em, set_time_em] = useState("");
+  const [recurrence_em, set_recurrence_em] = useState("");
   const [overall_em, set_overall_em] = useState("");
+  const [import_em, set_import_em] = useState("");
+  // A description of the result of importing a calendar file
+  const [import_result, set_import_result] = useState("");
 
   const [groups, set_groups] = useState([]);
 
@@ -45,9 +55,17 @@ function CreateEvent() {
   useEffect(() => {
     set_title_em("");
   }, [title]);
+  useEffect(() => {
+    set_recurrence_em("");
+  }, [frequency, interval, recurrence_end, until, count]);
   useEffect(() => {
     set_overall_em("");
-  }, [title, recurrence, visibility, start_time, end_time])
+  }, [title, frequency, interval, by_day, recurrence_end, until, count, visibility, start_time, end_time])
+
+  // The names of the days of the week, as used by the server
+  const days_of_the_week = ["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+  // The names of the periods for each frequency
+  const period_names = { Daily: "day(s)", Weekly: "week(s)", Monthly: "month(s)", Yearly: "year(s)" };
 
   return <PageContainerBox title="Create an event">
     {/* Disable form submission without clicking the button, as that refreshes the page without sending the data  */}
@@ -66,15 +84,73 @@ function CreateEvent() {
 
       <Form.Group className="mb-3" controlId="formBasicActivityRecurrence">
         <Form.Label>How often does this activity happen?</Form.Label>
-        <Form.Select value={recurrence} onChange={e => {
-          const new_recurrence = e.target.value;
-          set_recurrence(new_recurrence);
+        <Form.Select value={frequency} onChange={e => {
+          const new_frequency = e.target.value;
+          set_frequency(new_frequency);
         }}>
-          <option value="Weekly">Weekly</option>
           <option value="Once">Once</option>
+          <option value="Daily">Daily</option>
+          <option value="Weekly">Weekly</option>
+          <option value="Monthly">Monthly</option>
+          <option value="Yearly">Yearly</option>
         </Form.Select>
       </Form.Group>
 
+      {/* Only show the recurrence settings for events that repeat */}
+      {frequency !== "Once" && <>
+        <Form.Group className="mb-3" controlId="formBasicInterval">
+          <Form.Label>Repeat every how many {period_names[frequency]}?</Form.Label>
+          <Form.Control type="number" min="1" max="1000" value={interval} onChange={e => {
+            set_interval(parseInt(e.target.value));
+          }} />
+        </Form.Group>
+
+        {/* Yearly events can not be limited to days of the week */}
+        {frequency !== "Yearly" &&
+          <Form.Group className="mb-3" controlId="formBasicByDay">
+            <Form.Label>Only on these days (leave empty to use the day it starts on)</Form.Label>
+            <div>
+              {
+                days_of_the_week.map(day =>
+                  <Form.Check inline type="checkbox" label={day.substring(0, 3)} key={day} id={`by_day_${day}`}
+                    checked={by_day.includes(day)}
+                    onChange={e => {
+                      // Add or remove the day
+                      if (e.target.checked) set_by_day([...by_day, day]);
+                      else set_by_day(by_day.filter(el => el !== day));
+                    }} />
+                )
+              }
+            </div>
+          </Form.Group>
+        }
+
+        <Form.Group className="mb-3" controlId="formBasicRecurrenceEnd">
+          <Form.Label>When does it stop repeating?</Form.Label>
+          <Form.Select value={recurrence_end} onChange={e => {
+            set_recurrence_end(e.target.value);
+          }}>
+            <option value="Never">Never</option>
+            <option value="Until">On a date</option>
+            <option value="Count">After a number of times</option>
+          </Form.Select>
+          {recurrence_end === "Until" &&
+            <input type="datetime-local"
+              className="form-control mt-2"
+              value={time_to_time_string(until)}
+              onChange={e => set_until(time_string_to_time(e.target.value))}
+              onKeyDown={(e) => { e.key === "Enter" && e.preventDefault() }} // Prevent submitting the form by accident
+            />
+          }
+          {recurrence_end === "Count" &&
+            <Form.Control className="mt-2" type="number" min="1" value={count} onChange={e => {
+              set_count(parseInt(e.target.value));
+            }} />
+          }
+          <ErrorMessage em={recurrence_em} />
+        </Form.Group>
+      </>}
+
       <Form.Group className="mb-3" controlId="formBasicStartTime">
         <Form.Label>When does the activity start?</Form.Label>
         <input type="datetime-local"
@@ -140,12 +216,44 @@ function CreateEvent() {
       <Button variant="primary" onClick={submit}>
         Done
       </Button>
+
+      <Form.Group className="mt-4" controlId="formBasicImport">
+        <Form.Label>Or import the events from a calendar (.ics) file into the group</Form.Label>
+        <Form.Control type="file" accept=".ics,text/calendar" onChange={e => import_file(e.target.files[0])} />
+        <ErrorMessage em={import_em} />
+        {import_result !== "" && <div className="text-muted">{import_result}</div>}
+      </Form.Group>
     </Form>
   </PageContainerBox>;
 
+  async function import_file(file) {
+    set_import_em("");
+    set_import_result("");
+    if (file === undefined) return;
+
+    const res = await f("/api/import_calendar", "POST", {
+      group_id,
+      calendar: await file.text(),
+      // Times in the file without a time zone are in the user's time zone
+      time_zone: Intl.DateTimeFormat().resolvedOptions().timeZone
+    });
+
+    // if it was not successful, show the error message
+    if (res.status >= 400) {
+      // Read the error message
+      const error = await error_message(res);
+      return set_import_em(error);
+    }
+
+    // Tell the user which events could not be imported
+    const { imported, skipped } = await res.json();
+    const skipped_descriptions = skipped.map(el => `${el.summary || el.component}: ${el.reason}`);
+    set_import_result([`Imported ${imported} event(s).`, ...skipped_descriptions].join(" "));
+  }
+
   async function submit() {
     // Check if the error messages are shown and avoid proceeding if they are
-    if (title_em !== "" || time_em !== "") return;
+    if (title_em !== "" || time_em !== "" || recurrence_em !== "") return;
 
     // Check that the title is not empty
     if (title === "") return set_title_em("The title can not be empty");
@@ -157,6 +265,24 @@ function CreateEvent() {
     if (isNaN(start_timestamp) || isNaN(end_timestamp)) return set_time_em("Please enter valid times");
     if (duration < 60) return set_time_em("The event has to last at least 1 minute");
 
+    // Describe how the event repeats
+    const recurrence = { frequency };
+    if (frequency !== "Once") {
+      if (isNaN(interval) || interval < 1) return set_recurrence_em("Please enter a valid number of periods");
+      recurrence.interval = interval;
+      if (frequency !== "Yearly") recurrence.by_day = by_day;
+
+      if (recurrence_end === "Until") {
+        const until_timestamp = until.unix();
+        if (isNaN(until_timestamp)) return set_recurrence_em("Please enter a valid date");
+        if (until_timestamp < start_timestamp) return set_recurrence_em("The activity can not stop repeating before it has begun");
+        recurrence.until = until_timestamp;
+      } else if (recurrence_end === "Count") {
+        if (isNaN(count) || count < 1) return set_recurrence_em("The activity has to happen at least once");
+        recurrence.count = count;
+      }
+    }
+
     // Make the actual request
     const res = await f("/api/create_event", "POST", {
       title,
@@ -164,13 +290,15 @@ function CreateEvent() {
       recurrence,
       group_id,
       start_time: start_timestamp,
-      duration
+      duration,
+      // Repeat the event at the same local time, even when the clocks change
+      time_zone: Intl.DateTimeFormat().resolvedOptions().timeZone
     });
 
     // if it was not successful, show the error message
     if (res.status >= 400) {
       // Read the error message
-      const error = await res.text();
+      const error = await error_message(res);
       return set_overall_em(error);
     }
 
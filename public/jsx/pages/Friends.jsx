@@ -5,6 +5,7 @@ function Friends() {
   const [overall_em, set_overall_em] = useState("");
   const [friendship_groups, set_friendship_groups] = useState([]);
   const [friends, set_friends] = useState([]);
+  const [sent_requests, set_sent_requests] = useState([]);
   const [new_friend_username, set_new_friend_username] = useState("");
 
   // Fetch the data from the server
@@ -38,6 +39,7 @@ function Friends() {
   */
   function refresh_friends_list() {
     request("/api/get_friends", set_friends);
+    request("/api/get_friend_requests", data => set_sent_requests(data.outgoing));
   }
 
   /** A function to add a friend by username
@@ -52,7 +54,23 @@ function Friends() {
     // if it was not successful, show the error message
     if (res.status >= 400) {
       // Read the error message
-      const error = await res.text();
+      const error = await error_message(res);
+      return set_overall_em(error);
+    }
+
+    refresh_friends_list();
+  }
+
+  /** A function to cancel a friend request that has not been answered yet
+  */
+  async function cancel_friend_request(user_id) {
+    set_overall_em("");
+    const res = await f("/api/cancel_friend_request", "POST", { user_id });
+
+    // if it was not successful, show the error message
+    if (res.status >= 400) {
+      // Read the error message
+      const error = await error_message(res);
       return set_overall_em(error);
     }
 
@@ -69,7 +87,7 @@ function Friends() {
     // if it was not successful, show the error message
     if (res.status >= 400) {
       // Read the error message
-      const error = await res.text();
+      const error = await error_message(res);
       return set_overall_em(error);
     }
 
@@ -102,6 +120,21 @@ function Friends() {
       </div>
     </div>
     <ErrorMessage em={overall_em} />
+
+    {/* The friend requests that have not been answered yet */}
+    {sent_requests.length === 0 ? null : <div className="container-fluid p-0 mt-3">
+      <h5 className="text-center">Sent friend requests</h5>
+      <div className="row justify-content-start g-2">
+        {sent_requests.map((user, i) => <div className="col-6 col-md-3" key={i}>
+          <div className="text-center border rounded">
+            <div className="align-middle d-inline-block text-truncate" style={{ maxWidth: "70%" }}>{user.username}</div>
+            <span onClick={() => cancel_friend_request(user.id)}>
+              <i className="fas fa-times ps-1" style={{ cursor: "pointer" }} data-fa-transform="grow-5 down-4" />
+            </span>
+          </div>
+        </div>)}
+      </div>
+    </div>}
   </PageContainerBox >;
 
   /** An element of the page that shows the name of a friendship group and users, letting the users be added or deleted
@@ -131,7 +164,7 @@ function Friends() {
       // if it was not successful, show the error message
       if (res.status >= 400) {
         // Read the error message
-        const error = await res.text();
+        const error = await error_message(res);
         return set_overall_em(error);
       }
 
@@ -149,7 +182,7 @@ function Friends() {
       // if it was not successful, show the error message
       if (res.status >= 400) {
         // Read the error message
-        const error = await res.text();
+        const error = await error_message(res);
         return set_overall_em(error);
       }
 
@@ -167,7 +200,7 @@ function Friends() {
       // if it was not successful, show the error message
       if (res.status >= 400) {
         // Read the error message
-        const error = await res.text();
+        const error = await error_message(res);
         return set_overall_em(error);
       }
 
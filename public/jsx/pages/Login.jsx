@@ -4,18 +4,38 @@ function Login() {
   // Have variables to keep track of the state
   const [username, set_username] = useState("");
   const [password, set_password] = useState("");
+  // The code from the authenticator app, which is only asked for if the user has two-factor
+  // authentication turned on
+  const [code, set_code] = useState("");
+  const [needs_code, set_needs_code] = useState(false);
 
   // em is short for "error message"
   const [username_em, set_username_em] = useState("");
   const [password_em, set_password_em] = useState("");
+  const [code_em, set_code_em] = useState("");
   const [overall_em, set_overall_em] = useState("");
 
   // Remove the error messages when typing
   useEffect(() => {
     set_username_em("");
     set_password_em("");
+    set_code_em("");
     set_overall_em("");
-  }, [username, password]);
+  }, [username, password, code]);
+
+  if (needs_code) return <PageContainerBox title="Log in">
+    <Form>
+      <Form.Group className="mb-3" controlId="formBasicCode">
+        <Form.Label>Code from your authenticator app, or a recovery code</Form.Label>
+        <Form.Control type="text" autoComplete="one-time-code" placeholder="123456" value={code} onChange={e => set_code(e.target.value)} />
+        <ErrorMessage em={code_em} />
+      </Form.Group>
+      <ErrorMessage em={overall_em} />
+      <Button variant="primary" onClick={submit_code}>
+        Log in
+      </Button>
+    </Form>
+  </PageContainerBox>;
 
   return <PageContainerBox title="Log in">
     <Form>
@@ -50,12 +70,27 @@ function Login() {
     // if it was not successful, show the error message
     if (res.status >= 400) {
       // Read the error message
-      const error = await res.text();
+      const error = await error_message(res);
       return set_overall_em(error);
     }
 
+    // Users with two-factor authentication still have to enter a code
+    const body = await res.json();
+    if (body.two_factor_required) return set_needs_code(true);
 
     // Otherwise, refirect to the calendar page
     window.location.href = "/";
   }
+
+  async function submit_code() {
+    if (code.trim() === "") return set_code_em("Please enter a code");
+    const res = await f("/api/login_two_factor", "POST", { code });
+
+    if (res.status >= 400) {
+      const error = await error_message(res);
+      return set_overall_em(error);
+    }
+
+    window.location.href = "/";
+  }
 }
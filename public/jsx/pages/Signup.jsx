@@ -63,7 +63,7 @@ function Signup() {
     // if it was not successful, show the error message
     if (res.status >= 400) {
       // Read the error message
-      const error = await res.text();
+      const error = await error_message(res);
       return set_overall_em(error);
     }
 
@@ -0,0 +1,72 @@
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::{dev::ServiceRequest, http::Method};
+use openssl::memcmp;
+
+use crate::{
+    data::{models::random_token, session::bearer_token},
+    endpoints::EndpointError,
+};
+
+/// The key the CSRF token is stored under in the session cookie
+const CSRF_TOKEN_KEY: &str = "csrf_token";
+/// The header the pages send the CSRF token back in
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Get the CSRF token of the session, if it has one
+pub fn get_csrf_token(session: &Session) -> Option<String> {
+    session.get(CSRF_TOKEN_KEY).ok().flatten()
+}
+
+/// Store the CSRF token in the session, e.g. to keep it when the rest of the session is cleared
+pub fn set_csrf_token(session: &Session, token: &str) -> Result<(), SessionInsertError> {
+    session.insert(CSRF_TOKEN_KEY, token)
+}
+
+/// Get the CSRF token of the session, creating one if it does not have one yet.
+/// It is put in the pages, so that only the scripts on them can send it with their requests
+pub fn get_or_create_csrf_token(session: &Session) -> Result<String, SessionInsertError> {
+    if let Some(token) = get_csrf_token(session) {
+        return Ok(token);
+    }
+
+    let token = random_token();
+    set_csrf_token(session, &token)?;
+    Ok(token)
+}
+
+/// Check that a request that can change something was sent by one of the pages of the site, by
+/// comparing the token in its header with the one in its session cookie. Other sites can make the
+/// browser send the cookie, but they can not read the token from the pages.
+/// Requests with an API token are not checked, as browsers do not send that header by themselves
+pub fn check_csrf_token(req: &ServiceRequest) -> Result<(), EndpointError> {
+    if matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || bearer_token(req.request()).is_some()
+    {
+        return Ok(());
+    }
+
+    let expected = get_csrf_token(&req.get_session());
+    let provided = req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    match (expected, provided) {
+        (Some(expected), Some(provided))
+            if expected.len() == provided.len()
+                && memcmp::eq(expected.as_bytes(), provided.as_bytes()) =>
+        {
+            Ok(())
+        }
+        _ => {
+            log::warn!(
+                "csrf.check_csrf_token: rejected {} {}",
+                req.method(),
+                req.path()
+            );
+            Err(EndpointError::NotAllowed(
+                "This page has expired. Please reload it and try again.",
+            ))
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use openssl::sha::sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::{api_tokens, users};
+
+use super::{
+    models::{random_token, UnsavedModel},
+    users::User,
+};
+
+/// The start of every API token, so that leaked tokens are easy to recognise
+const TOKEN_PREFIX: &str = "cal_";
+
+/// What an API token is allowed to do
+#[derive(
+    Serialize,
+    Deserialize,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Debug,
+    Clone,
+    Copy,
+    diesel_derive_enum::DbEnum,
+)]
+#[DieselTypePath = "crate::schema::sql_types::TokenScope"]
+pub enum TokenScope {
+    /// See the events and the notifications of the user
+    ReadCalendar,
+    /// Create and change groups and their participants
+    ManageGroups,
+    /// Create, change and import events
+    ManageEvents,
+}
+
+impl TokenScope {
+    /// Get the scope a token needs for the endpoint with this route.
+    /// Endpoints that are not listed, e.g. the ones for the account or for API tokens themselves,
+    /// can only be used with a session
+    pub fn required_for(route: &str) -> Option<TokenScope> {
+        match route {
+            "/api/get_events" | "/api/get_notifications" => Some(TokenScope::ReadCalendar),
+            "/api/create_event"
+            | "/api/cancel_event_occurrence"
+            | "/api/reschedule_event_occurrence"
+            | "/api/update_event"
+            | "/api/delete_event"
+            | "/api/import_calendar" => Some(TokenScope::ManageEvents),
+            // The friends are needed to know who can be invited to a group
+            "/api/get_friends"
+            | "/api/get_owned_groups_with_participants"
+            | "/api/create_group"
+            | "/api/invite_to_group"
+            | "/api/rename_group"
+            | "/api/remove_user_from_group"
+            | "/api/reply_to_group_invitation" => Some(TokenScope::ManageGroups),
+            _ => None,
+        }
+    }
+}
+
+/// A struct that represents a personal token a user's scripts can use the API with
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(User, foreign_key = user_id))]
+#[diesel(table_name = api_tokens)]
+pub struct ApiToken {
+    pub id: i32,
+    pub user_id: i32,
+    pub name: String,
+    /// Only the hash is stored, so that the tokens can not be used by someone who reads the
+    /// database
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+/// A version of the API token struct that can be sent to the frontend, i.e. without the hash
+#[derive(Serialize)]
+pub struct ApiTokenPublic {
+    pub id: i32,
+    pub name: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: i64,
+    pub last_used_at: Option<i64>,
+}
+
+/// An API token that has not been saved to the database yet
+#[derive(Insertable)]
+#[diesel(table_name = api_tokens)]
+pub struct UnsavedApiToken {
+    pub user_id: i32,
+    pub name: String,
+    pub token_hash: String,
+    pub scopes: Vec<TokenScope>,
+    pub created_at: i64,
+}
+
+impl UnsavedApiToken {
+    /// Create a token for the user with a new random secret, which is returned with it as it is
+    /// not stored
+    pub fn new(user: &User, name: String, scopes: Vec<TokenScope>) -> (Self, String) {
+        let token = format!("{TOKEN_PREFIX}{}", random_token());
+
+        let unsaved_token = UnsavedApiToken {
+            user_id: user.id,
+            name,
+            token_hash: hash_token(&token),
+            scopes,
+            created_at: Utc::now().timestamp(),
+        };
+
+        (unsaved_token, token)
+    }
+}
+
+impl UnsavedModel<ApiToken> for UnsavedApiToken {
+    fn save(self, connection: &mut PgConnection) -> QueryResult<ApiToken> {
+        diesel::insert_into(api_tokens::dsl::api_tokens)
+            .values(self)
+            .get_result(connection)
+    }
+}
+
+impl ApiToken {
+    /// Convert to the public version
+    pub fn to_public(self) -> ApiTokenPublic {
+        ApiTokenPublic {
+            id: self.id,
+            name: self.name,
+            scopes: self.scopes,
+            created_at: self.created_at,
+            last_used_at: self.last_used_at,
+        }
+    }
+
+    /// Find the token and the user it belongs to, recording that it was used
+    pub fn get_with_user_by_token(
+        connection: &mut PgConnection,
+        token: &str,
+    ) -> QueryResult<Option<(ApiToken, User)>> {
+        let token_with_user: Option<(ApiToken, User)> = api_tokens::table
+            .inner_join(users::table)
+            .filter(api_tokens::token_hash.eq(hash_token(token)))
+            .first(connection)
+            .optional()?;
+
+        if let Some((api_token, _)) = &token_with_user {
+            diesel::update(api_token)
+                .set(api_tokens::last_used_at.eq(Utc::now().timestamp()))
+                .execute(connection)?;
+        }
+
+        Ok(token_with_user)
+    }
+
+    /// Get all the tokens of the user, the newest first
+    pub fn get_all_for_user(
+        connection: &mut PgConnection,
+        user_id: i32,
+    ) -> QueryResult<Vec<ApiToken>> {
+        api_tokens::table
+            .filter(api_tokens::user_id.eq(user_id))
+            .order(api_tokens::created_at.desc())
+            .load(connection)
+    }
+
+    /// Delete a token of the user so that it stops working, returning whether it existed
+    pub fn delete_for_user(
+        connection: &mut PgConnection,
+        user_id: i32,
+        token_id: i32,
+    ) -> QueryResult<bool> {
+        let deleted = diesel::delete(
+            api_tokens::table
+                .filter(api_tokens::id.eq(token_id))
+                .filter(api_tokens::user_id.eq(user_id)),
+        )
+        .execute(connection)?;
+
+        Ok(deleted == 1)
+    }
+
+    /// Delete all the tokens of the user, e.g. after the password is changed
+    pub fn delete_all_for_user(connection: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
+        diesel::delete(api_tokens::table.filter(api_tokens::user_id.eq(user_id)))
+            .execute(connection)
+    }
+}
+
+/// Hash a token so that it can be looked up. The tokens are long and random, so unlike passwords
+/// they do not need a slow hash
+fn hash_token(token: &str) -> String {
+    sha256(token.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
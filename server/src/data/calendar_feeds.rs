@@ -0,0 +1,77 @@
+use diesel::prelude::*;
+
+use crate::schema::{calendar_feeds, users};
+
+use super::{
+    models::{random_token, UnsavedModel},
+    users::User,
+};
+
+/// A struct that represents the secret token a user's calendar can be subscribed to with
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(User, foreign_key = user_id))]
+#[diesel(table_name = calendar_feeds)]
+pub struct CalendarFeed {
+    pub id: i32,
+    pub user_id: i32,
+    pub token: String,
+}
+
+/// A calendar feed that has not been saved to the database yet
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = calendar_feeds)]
+pub struct UnsavedCalendarFeed {
+    pub user_id: i32,
+    pub token: String,
+}
+
+impl UnsavedCalendarFeed {
+    /// Create a feed for the user with a new random token
+    pub fn new(user: &User) -> Self {
+        UnsavedCalendarFeed {
+            user_id: user.id,
+            token: random_token(),
+        }
+    }
+}
+
+impl CalendarFeed {
+    /// Get the feed of the user, creating it if it does not exist yet
+    pub fn get_or_create(connection: &mut PgConnection, user: &User) -> QueryResult<CalendarFeed> {
+        let feed = calendar_feeds::table
+            .filter(calendar_feeds::user_id.eq(user.id))
+            .first(connection)
+            .optional()?;
+
+        match feed {
+            Some(feed) => Ok(feed),
+            None => UnsavedCalendarFeed::new(user).save(connection),
+        }
+    }
+
+    /// Find the user that the feed with this token belongs to
+    pub fn get_user_by_token(
+        connection: &mut PgConnection,
+        token: &str,
+    ) -> QueryResult<Option<User>> {
+        users::table
+            .inner_join(calendar_feeds::table)
+            .filter(calendar_feeds::token.eq(token))
+            .select(users::all_columns)
+            .first(connection)
+            .optional()
+    }
+}
+
+impl UnsavedModel<CalendarFeed> for UnsavedCalendarFeed {
+    /// Save the feed, replacing the token of the user's existing feed so that the old URL stops
+    /// working
+    fn save(self, connection: &mut PgConnection) -> QueryResult<CalendarFeed> {
+        diesel::insert_into(calendar_feeds::dsl::calendar_feeds)
+            .values(&self)
+            .on_conflict(calendar_feeds::user_id)
+            .do_update()
+            .set(&self)
+            .get_result(connection)
+    }
+}
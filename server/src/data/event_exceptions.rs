@@ -0,0 +1,91 @@
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::event_exceptions;
+
+use super::{events::Event, models::UnsavedModel};
+
+/// A struct that represents a change to a single occurrence of an event, such as it being
+/// cancelled or moved to a different time
+#[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
+#[diesel(belongs_to(Event, foreign_key = event_id))]
+#[diesel(table_name = event_exceptions)]
+pub struct EventException {
+    pub id: i32,
+    pub event_id: i32,
+    /// The time the occurrence was originally scheduled for, as a UNIX timestamp in seconds
+    pub original_start_time: i64,
+    pub is_cancelled: bool,
+    /// The time the occurrence was moved to, if it was moved
+    pub new_start_time: Option<i64>,
+    /// The new duration of the occurrence, if it was changed
+    pub new_duration: Option<i64>,
+}
+
+/// An exception that has not been saved to the database yet
+#[derive(Insertable, AsChangeset)]
+#[diesel(table_name = event_exceptions)]
+#[diesel(treat_none_as_null = true)]
+pub struct UnsavedEventException {
+    pub event_id: i32,
+    pub original_start_time: i64,
+    pub is_cancelled: bool,
+    pub new_start_time: Option<i64>,
+    pub new_duration: Option<i64>,
+}
+
+impl EventException {
+    /// Get all the exceptions to the events with these ids
+    pub fn get_exceptions_for_events(
+        connection: &mut PgConnection,
+        event_ids: &[i32],
+    ) -> QueryResult<Vec<EventException>> {
+        event_exceptions::table
+            .filter(event_exceptions::event_id.eq_any(event_ids))
+            .load(connection)
+    }
+
+    /// Delete the exceptions to the occurrences of an event that were originally scheduled at or
+    /// after `from`
+    pub fn delete_exceptions_for_event(
+        connection: &mut PgConnection,
+        event_id: i32,
+        from: i64,
+    ) -> QueryResult<usize> {
+        diesel::delete(event_exceptions::table)
+            .filter(
+                event_exceptions::event_id
+                    .eq(event_id)
+                    .and(event_exceptions::original_start_time.ge(from)),
+            )
+            .execute(connection)
+    }
+
+    /// Get the time and duration an occurrence of `event` happens at after this exception has
+    /// been applied, or None if the occurrence was cancelled
+    pub fn rescheduled_time(&self, event: &Event) -> Option<(i64, i64)> {
+        if self.is_cancelled {
+            None
+        } else {
+            Some((
+                self.new_start_time.unwrap_or(self.original_start_time),
+                self.new_duration.unwrap_or(event.duration),
+            ))
+        }
+    }
+}
+
+impl UnsavedModel<EventException> for UnsavedEventException {
+    /// Save the exception, replacing any existing exception for the same occurrence
+    fn save(self, connection: &mut PgConnection) -> QueryResult<EventException> {
+        diesel::insert_into(event_exceptions::dsl::event_exceptions)
+            .values(&self)
+            .on_conflict((
+                event_exceptions::event_id,
+                event_exceptions::original_start_time,
+            ))
+            .do_update()
+            .set(&self)
+            .get_result(connection)
+    }
+}
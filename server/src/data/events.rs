@@ -1,21 +1,18 @@
+use chrono_tz::Tz;
 pub use diesel::{connection, prelude::*};
 use serde::{Deserialize, Serialize};
 
 use crate::schema::{events, groups, groups_participants};
 
 use super::{
+    event_exceptions::EventException,
     group::{Group, ParticipationType},
     models::UnsavedModel,
+    recurrence::{occurrence_start_times, Frequency, RecurrenceRule, Weekday},
     users::User,
 };
 
-#[derive(Serialize, Deserialize, PartialEq, Debug, diesel_derive_enum::DbEnum)]
-#[DieselTypePath = "crate::schema::sql_types::RecurrenceType"]
-pub enum RecurrenceType {
-    Weekly,
-    Once,
-}
-#[derive(Serialize, Deserialize, PartialEq, Debug, diesel_derive_enum::DbEnum)]
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, diesel_derive_enum::DbEnum)]
 #[DieselTypePath = "crate::schema::sql_types::VisibilityType"]
 pub enum VisibilityType {
     Public,
@@ -29,10 +26,13 @@ pub enum VisibilityType {
 pub struct UnsavedEvent {
     pub title: String,
     pub visibility: VisibilityType,
-    pub start_time: i32,
-    pub duration: i32,
-    pub recurrence: RecurrenceType,
+    pub start_time: i64,
+    pub duration: i64,
     pub group_id: i32,
+    #[diesel(embed)]
+    pub recurrence: RecurrenceRule,
+    /// The IANA name of the time zone the event repeats in
+    pub time_zone: String,
 }
 
 #[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
@@ -43,13 +43,107 @@ pub struct Event {
     pub id: i32,
     pub title: String,
     pub visibility: VisibilityType,
-    pub start_time: i32,
-    pub duration: i32,
-    pub recurrence_type: RecurrenceType,
+    pub start_time: i64,
+    pub duration: i64,
     pub group_id: i32,
+    pub recurrence_frequency: Frequency,
+    pub recurrence_interval: i32,
+    pub recurrence_by_day: Vec<Weekday>,
+    pub recurrence_until: Option<i64>,
+    pub recurrence_count: Option<i32>,
+    /// The IANA name of the time zone the event repeats in
+    pub time_zone: String,
 }
 
 impl Event {
+    /// Get the event by id
+    pub fn get_event_by_id(
+        connection: &mut PgConnection,
+        event_id: i32,
+    ) -> QueryResult<Option<Event>> {
+        events::table.find(event_id).first(connection).optional()
+    }
+
+    /// Change the title, visibility and timing of an event to those in `changes`.
+    /// The group of the event stays the same.
+    /// The exceptions to its occurrences are removed if the timing changes, as they may no longer
+    /// refer to the same occurrences
+    pub fn update(
+        connection: &mut PgConnection,
+        event: &Event,
+        changes: UnsavedEvent,
+    ) -> QueryResult<Event> {
+        connection.transaction(|connection| {
+            if changes.start_time != event.start_time
+                || changes.recurrence != event.recurrence_rule()
+                || changes.time_zone != event.time_zone
+            {
+                EventException::delete_exceptions_for_event(connection, event.id, i64::MIN)?;
+            }
+
+            diesel::update(events::table.find(event.id))
+                .set((
+                    events::title.eq(changes.title),
+                    events::visibility.eq(changes.visibility),
+                    events::start_time.eq(changes.start_time),
+                    events::duration.eq(changes.duration),
+                    &changes.recurrence,
+                    events::time_zone.eq(changes.time_zone),
+                ))
+                .get_result(connection)
+        })
+    }
+
+    /// Delete an event together with the exceptions to its occurrences
+    pub fn delete(connection: &mut PgConnection, event_id: i32) -> QueryResult<usize> {
+        connection.transaction(|connection| {
+            EventException::delete_exceptions_for_event(connection, event_id, i64::MIN)?;
+            diesel::delete(events::table.find(event_id)).execute(connection)
+        })
+    }
+
+    /// Stop a recurring event from happening at or after `time`, keeping the earlier occurrences
+    pub fn end_before(
+        connection: &mut PgConnection,
+        event: &Event,
+        time: i64,
+    ) -> QueryResult<Event> {
+        let mut recurrence = event.recurrence_rule();
+        if recurrence.count.is_some() {
+            // Only keep the occurrences that start before this time
+            let count = occurrence_start_times(event, event.start_time, time).len();
+            recurrence.count = Some(count as i32);
+        } else {
+            recurrence.until = Some(time - 1);
+        }
+
+        connection.transaction(|connection| {
+            // The later exceptions no longer refer to any occurrences
+            EventException::delete_exceptions_for_event(connection, event.id, time)?;
+
+            diesel::update(events::table.find(event.id))
+                .set(&recurrence)
+                .get_result(connection)
+        })
+    }
+
+    /// Get the time zone the event repeats in.
+    /// The time zones are checked when events are saved, so UTC is only used as a fallback
+    pub fn time_zone(&self) -> Tz {
+        self.time_zone.parse().unwrap_or(Tz::UTC)
+    }
+
+    /// Get the rule that describes when the event repeats
+    pub fn recurrence_rule(&self) -> RecurrenceRule {
+        RecurrenceRule {
+            frequency: self.recurrence_frequency,
+            interval: self.recurrence_interval,
+            by_day: self.recurrence_by_day.clone(),
+            until: self.recurrence_until,
+            count: self.recurrence_count,
+        }
+    }
+
     /// get all events that were created by a group that the user is an admin of
     pub fn get_events_owned_by_user(
         connection: &mut PgConnection,
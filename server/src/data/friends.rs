@@ -1,12 +1,21 @@
 use crate::schema::{friendships, users};
-use diesel::dsl::count;
+use diesel::result::{DatabaseErrorKind, Error};
 pub use diesel::{connection, prelude::*};
 use serde::{Deserialize, Serialize};
 
 use super::{models::UnsavedModel, users::User};
 
-/// A struct that represents a friend relation between 2 users. It is unidirectional, with the
-/// "owner" being the person who initiated the friendship
+/// Whether the friend has accepted the friendship yet
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy, diesel_derive_enum::DbEnum)]
+#[DieselTypePath = "crate::schema::sql_types::FriendshipStatus"]
+pub enum FriendshipStatus {
+    /// The owner asked to be friends and the friend has not replied yet
+    Pending,
+    Accepted,
+}
+
+/// A struct that represents a friend relation between 2 users, with the "owner" being the person
+/// who sent the friend request. There is only one row for every pair of users
 #[derive(Identifiable, Queryable, Associations, PartialEq, Debug, Serialize, Deserialize)]
 #[diesel(belongs_to(User, foreign_key = owner_id))]
 #[diesel(table_name = friendships)]
@@ -14,6 +23,7 @@ pub struct Friendship {
     pub id: i32,
     owner_id: i32,
     friend_id: i32,
+    status: FriendshipStatus,
 }
 
 /// A struct for a friendship that can be saved in the database
@@ -22,34 +32,93 @@ pub struct Friendship {
 pub struct UnsavedFrienship {
     owner_id: i32,
     friend_id: i32,
+    status: FriendshipStatus,
 }
 
 /// An enum used to designate the result of adding a friend, whether it was successful and why it
 /// failed
-#[derive(PartialEq, Eq)]
 pub enum FriendAddResult {
     UsernameNotFound,
     TriedFriendThemselves,
     AlreadyFriends,
-    Success,
+    /// There is a friend request from the user that has not been answered yet
+    AlreadyRequested,
+    /// A friend request was sent to the user
+    RequestSent(User),
+    /// The user had sent a friend request already, which was accepted
+    RequestAccepted(User),
+}
+
+/// The friend requests a user has sent and received that have not been answered yet
+pub struct FriendRequests {
+    pub incoming: Vec<User>,
+    pub outgoing: Vec<User>,
 }
 
 impl Friendship {
-    /// A function to get all friends of a user (unidirectional)
+    /// A function to get all friends of a user, i.e. the users whose friendship has been accepted,
+    /// whoever sent the request
     pub fn get_friends(
         connection: &mut PgConnection,
         user: &User,
     ) -> Result<Vec<User>, diesel::result::Error> {
-        // Query all the friends where the user is the "owner" of the friendship
+        // The user can be on either side of the friendship
         let friends_vec = users::table
-            .inner_join(friendships::table.on(friendships::friend_id.eq(users::id)))
-            .filter(friendships::owner_id.eq(user.id))
+            .inner_join(
+                friendships::table.on(friendships::friend_id
+                    .eq(users::id)
+                    .and(friendships::owner_id.eq(user.id))
+                    .or(friendships::owner_id
+                        .eq(users::id)
+                        .and(friendships::friend_id.eq(user.id)))),
+            )
+            .filter(friendships::status.eq(FriendshipStatus::Accepted))
             .select(users::all_columns)
             .load::<User>(connection)?;
         Ok(friends_vec)
     }
 
-    /// A function to add a friend (unidirectional)
+    /// A function to get the users who have sent a friend request to the user, which has not been
+    /// answered yet
+    pub fn get_incoming_requests(
+        connection: &mut PgConnection,
+        user: &User,
+    ) -> Result<Vec<User>, diesel::result::Error> {
+        users::table
+            .inner_join(friendships::table.on(friendships::owner_id.eq(users::id)))
+            .filter(
+                friendships::friend_id
+                    .eq(user.id)
+                    .and(friendships::status.eq(FriendshipStatus::Pending)),
+            )
+            .select(users::all_columns)
+            .load::<User>(connection)
+    }
+
+    /// A function to get the friend requests that the user has sent and received, which have not
+    /// been answered yet
+    pub fn get_requests(
+        connection: &mut PgConnection,
+        user: &User,
+    ) -> Result<FriendRequests, diesel::result::Error> {
+        let outgoing = users::table
+            .inner_join(friendships::table.on(friendships::friend_id.eq(users::id)))
+            .filter(
+                friendships::owner_id
+                    .eq(user.id)
+                    .and(friendships::status.eq(FriendshipStatus::Pending)),
+            )
+            .select(users::all_columns)
+            .load::<User>(connection)?;
+
+        Ok(FriendRequests {
+            incoming: Self::get_incoming_requests(connection, user)?,
+            outgoing,
+        })
+    }
+
+    /// A function to add a friend. This sends them a friend request, or accepts theirs if they
+    /// have sent one already
     pub fn add_friend(
         connection: &mut PgConnection,
         friendship_owner: &User,
@@ -73,31 +142,112 @@ impl Friendship {
         let friend = found_users.pop();
 
         if let Some(friend) = friend {
-            // Check if the friendship exists
-            // No need to worry about concurrent access, since the connection is protected by a mutex.
-            let number_of_existing_friendships: i64 = friendships::table
+            // Check if there is a friendship or a request in either direction
+            let existing_friendship: Option<Friendship> = friendships::table
                 .filter(
                     friendships::owner_id
                         .eq(friendship_owner.id)
-                        .and(friendships::friend_id.eq(friend.id)),
+                        .and(friendships::friend_id.eq(friend.id))
+                        .or(friendships::owner_id
+                            .eq(friend.id)
+                            .and(friendships::friend_id.eq(friendship_owner.id))),
                 )
-                .select(count(friendships::id))
-                .first(connection)?;
-            if number_of_existing_friendships != 0 {
-                return Ok(FriendAddResult::AlreadyFriends);
+                .first(connection)
+                .optional()?;
+
+            match existing_friendship {
+                Some(Friendship {
+                    status: FriendshipStatus::Accepted,
+                    ..
+                }) => return Ok(FriendAddResult::AlreadyFriends),
+                Some(Friendship { owner_id, .. }) if owner_id == friendship_owner.id => {
+                    return Ok(FriendAddResult::AlreadyRequested)
+                }
+                // The other user wants to be friends as well
+                Some(_) => {
+                    return if Self::accept_request(connection, friendship_owner, friend.id)? {
+                        Ok(FriendAddResult::RequestAccepted(friend))
+                    } else {
+                        // The request was cancelled or answered in the meantime
+                        Ok(FriendAddResult::AlreadyRequested)
+                    };
+                }
+                None => {}
             }
 
             let friendship = UnsavedFrienship {
                 owner_id: friendship_owner.id,
                 friend_id: friend.id,
+                status: FriendshipStatus::Pending,
             };
-            friendship.save(connection)?;
-
-            Ok(FriendAddResult::Success)
+            match friendship.save(connection) {
+                // A friendship or a request was added by another request in the meantime
+                Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                    Ok(FriendAddResult::AlreadyRequested)
+                }
+                Err(err) => Err(err),
+                Ok(_) => Ok(FriendAddResult::RequestSent(friend)),
+            }
         } else {
             Ok(FriendAddResult::UsernameNotFound)
         }
     }
+
+    /// A function to accept a friend request that the user received, returning whether there was
+    /// such a request
+    pub fn accept_request(
+        connection: &mut PgConnection,
+        user: &User,
+        requester_id: i32,
+    ) -> Result<bool, diesel::result::Error> {
+        let updated = diesel::update(
+            friendships::table
+                .filter(friendships::owner_id.eq(requester_id))
+                .filter(friendships::friend_id.eq(user.id))
+                .filter(friendships::status.eq(FriendshipStatus::Pending)),
+        )
+        .set(friendships::status.eq(FriendshipStatus::Accepted))
+        .execute(connection)?;
+
+        Ok(updated == 1)
+    }
+
+    /// A function to decline a friend request that the user received, returning whether there was
+    /// such a request
+    pub fn decline_request(
+        connection: &mut PgConnection,
+        user: &User,
+        requester_id: i32,
+    ) -> Result<bool, diesel::result::Error> {
+        Self::delete_request(connection, requester_id, user.id)
+    }
+
+    /// A function to cancel a friend request that the user sent, returning whether there was such
+    /// a request
+    pub fn cancel_request(
+        connection: &mut PgConnection,
+        user: &User,
+        recipient_id: i32,
+    ) -> Result<bool, diesel::result::Error> {
+        Self::delete_request(connection, user.id, recipient_id)
+    }
+
+    /// Delete a friend request that has not been answered yet
+    fn delete_request(
+        connection: &mut PgConnection,
+        owner_id: i32,
+        friend_id: i32,
+    ) -> Result<bool, diesel::result::Error> {
+        let deleted = diesel::delete(
+            friendships::table
+                .filter(friendships::owner_id.eq(owner_id))
+                .filter(friendships::friend_id.eq(friend_id))
+                .filter(friendships::status.eq(FriendshipStatus::Pending)),
+        )
+        .execute(connection)?;
+
+        Ok(deleted == 1)
+    }
 }
 
 impl UnsavedModel<Friendship> for UnsavedFrienship {
@@ -103,6 +103,16 @@ impl Group {
         groups::table.find(group_id).first(connection).optional()
     }
 
+    /// Get the groups with these ids
+    pub fn get_groups_by_ids(
+        connection: &mut PgConnection,
+        group_ids: &[i32],
+    ) -> QueryResult<Vec<Group>> {
+        groups::table
+            .filter(groups::id.eq_any(group_ids))
+            .load(connection)
+    }
+
     pub fn rename_group_by_id(
         connection: &mut PgConnection,
         group_id: i32,
@@ -140,7 +150,7 @@ impl Group {
         group_id: i32,
         user: &User,
         decision: ParticipationType,
-    ) -> QueryResult<usize> {
+    ) -> QueryResult<GroupParticipant> {
         diesel::update(groups_participants::table)
             .filter(
                 groups_participants::group_id
@@ -148,7 +158,7 @@ impl Group {
                     .and(groups_participants::participant_id.eq(user.id)),
             )
             .set(groups_participants::participation_type.eq(decision))
-            .execute(connection)
+            .get_result(connection)
     }
 }
 
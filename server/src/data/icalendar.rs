@@ -0,0 +1,1429 @@
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use chrono::{
+    DateTime, Datelike, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset, TimeZone, Utc,
+};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use serde::Serialize;
+
+use crate::settings::MAX_EVENT_TITLE_LENGTH;
+
+use super::{
+    event_exceptions::EventException,
+    events::{Event, UnsavedEvent, VisibilityType},
+    group::Group,
+    recurrence::{is_occurrence, to_local_time, to_timestamp, Frequency, RecurrenceRule, Weekday},
+};
+
+/// The longest a content line can be, in octets, before it has to be folded
+const MAX_LINE_LENGTH: usize = 75;
+/// The earliest year that the changes of the UTC offsets of time zones are exported from. The
+/// offsets before it are the same as at its start
+const FIRST_TIME_ZONE_YEAR: i32 = 1970;
+/// The most VTIMEZONE components that are kept to be exported again
+const MAX_CACHED_TIME_ZONES: usize = 1000;
+
+/// Get the code used for a day of the week in the BYDAY part of a recurrence rule
+pub fn weekday_code(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Monday => "MO",
+        Weekday::Tuesday => "TU",
+        Weekday::Wednesday => "WE",
+        Weekday::Thursday => "TH",
+        Weekday::Friday => "FR",
+        Weekday::Saturday => "SA",
+        Weekday::Sunday => "SU",
+    }
+}
+
+/// Get the day of the week from its code in the BYDAY part of a recurrence rule
+fn weekday_from_code(code: &str) -> Option<Weekday> {
+    match code {
+        "MO" => Some(Weekday::Monday),
+        "TU" => Some(Weekday::Tuesday),
+        "WE" => Some(Weekday::Wednesday),
+        "TH" => Some(Weekday::Thursday),
+        "FR" => Some(Weekday::Friday),
+        "SA" => Some(Weekday::Saturday),
+        "SU" => Some(Weekday::Sunday),
+        _ => None,
+    }
+}
+
+/// Format a UNIX timestamp in seconds as an iCalendar date-time in UTC
+pub fn format_time(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// Format the property `name` with a UNIX timestamp in seconds as its value. Times in zones other
+/// than UTC are written as local times with a TZID, so that clients repeat them in wall-clock time
+fn time_property(name: &str, timestamp: i64, time_zone: Tz) -> String {
+    match to_local_time(timestamp, time_zone) {
+        Some(time) if time_zone != Tz::UTC => format!(
+            "{};TZID={}:{}",
+            name,
+            time_zone.name(),
+            time.format("%Y%m%dT%H%M%S")
+        ),
+        _ => format!("{}:{}", name, format_time(timestamp)),
+    }
+}
+
+/// Escape the characters that have a special meaning in iCalendar text values
+fn escape_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+/// Reverse the escaping of iCalendar text values
+fn unescape_text(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut characters = text.chars();
+    while let Some(character) = characters.next() {
+        if character == '\\' {
+            match characters.next() {
+                Some('n') | Some('N') => result.push('\n'),
+                Some(escaped) => result.push(escaped),
+                None => {}
+            }
+        } else {
+            result.push(character);
+        }
+    }
+    result
+}
+
+/// A helper to build the text of an iCalendar object, one content line at a time
+struct CalendarWriter {
+    text: String,
+}
+
+impl CalendarWriter {
+    /// Add a content line, splitting it into several lines if it is too long
+    fn line(&mut self, line: &str) {
+        let mut line_length = 0;
+        for character in line.chars() {
+            // Continuation lines start with a space, which counts towards their length
+            if line_length + character.len_utf8() > MAX_LINE_LENGTH {
+                self.text.push_str("\r\n ");
+                line_length = 1;
+            }
+            self.text.push(character);
+            line_length += character.len_utf8();
+        }
+        self.text.push_str("\r\n");
+    }
+}
+
+/// Get the value of the RRULE property for a recurrence rule, or None if the event does not
+/// repeat
+fn recurrence_rule_value(rule: &RecurrenceRule) -> Option<String> {
+    let frequency = match rule.frequency {
+        Frequency::Once => return None,
+        Frequency::Daily => "DAILY",
+        Frequency::Weekly => "WEEKLY",
+        Frequency::Monthly => "MONTHLY",
+        Frequency::Yearly => "YEARLY",
+    };
+
+    let mut value = format!("FREQ={}", frequency);
+    if rule.interval != 1 {
+        value.push_str(&format!(";INTERVAL={}", rule.interval));
+    }
+    if !rule.by_day.is_empty() {
+        let days: Vec<_> = rule.by_day.iter().map(|day| weekday_code(*day)).collect();
+        value.push_str(&format!(";BYDAY={}", days.join(",")));
+    }
+    if let Some(until) = rule.until {
+        value.push_str(&format!(";UNTIL={}", format_time(until)));
+    }
+    if let Some(count) = rule.count {
+        value.push_str(&format!(";COUNT={}", count));
+    }
+
+    Some(value)
+}
+
+/// The UTC offset of a time zone at some moment
+#[derive(PartialEq, Clone, Debug)]
+struct ZoneOffset {
+    /// The number of seconds the local time is ahead of UTC
+    seconds: i32,
+    /// Whether it is daylight saving time
+    is_daylight: bool,
+    /// The abbreviation of the offset, e.g. BST
+    name: String,
+}
+
+/// A change of the UTC offset of a time zone, e.g. the start of daylight saving time
+struct OffsetChange {
+    /// The UNIX timestamp, in seconds, at which the new offset starts
+    time: i64,
+    from: ZoneOffset,
+    to: ZoneOffset,
+}
+
+/// The changes of the UTC offset of a time zone that happen in the same way every year, e.g. on
+/// the last Sunday in March at 01:00. A single change is a series of its own
+struct OffsetChangeSeries {
+    from: ZoneOffset,
+    to: ZoneOffset,
+    /// The local time of the first change, before it happens
+    first_local_time: NaiveDateTime,
+    /// The month, the week of the month and the day of the week of the changes, e.g. `3`, `-1`
+    /// and `SU` for the last Sunday in March, and their local time
+    rule: (u32, i32, &'static str, NaiveTime),
+    last_year: i32,
+    last_time: i64,
+    count: usize,
+}
+
+/// Get the UTC offset of a time zone at a UNIX timestamp in seconds
+fn zone_offset(time_zone: Tz, timestamp: i64) -> Option<ZoneOffset> {
+    let time = DateTime::from_timestamp(timestamp, 0)?.naive_utc();
+    let offset = time_zone.offset_from_utc_datetime(&time);
+    Some(ZoneOffset {
+        seconds: offset.fix().local_minus_utc(),
+        is_daylight: offset.dst_offset() > chrono::Duration::zero(),
+        name: offset.abbreviation().to_string(),
+    })
+}
+
+/// Find the changes of the UTC offset of a time zone between the UNIX timestamps `from` and `to`
+fn offset_changes(time_zone: Tz, from: i64, to: i64) -> Vec<OffsetChange> {
+    let mut changes = vec![];
+    let (mut time, Some(mut offset)) = (from, zone_offset(time_zone, from)) else {
+        return changes;
+    };
+
+    while time < to {
+        // The offsets change at most a few times a year, so it is enough to check every day
+        let next_time = (time + 24 * 60 * 60).min(to);
+        match zone_offset(time_zone, next_time) {
+            Some(next_offset) if next_offset != offset => {
+                // Find the first second with the new offset
+                let (mut before, mut after) = (time, next_time);
+                while after - before > 1 {
+                    let middle = before + (after - before) / 2;
+                    if zone_offset(time_zone, middle).as_ref() == Some(&offset) {
+                        before = middle;
+                    } else {
+                        after = middle;
+                    }
+                }
+                let Some(new_offset) = zone_offset(time_zone, after) else {
+                    break;
+                };
+                changes.push(OffsetChange {
+                    time: after,
+                    from: offset,
+                    to: new_offset.clone(),
+                });
+                // There may be another change on the same day
+                time = after;
+                offset = new_offset;
+            }
+            Some(_) => time = next_time,
+            None => break,
+        }
+    }
+
+    changes
+}
+
+/// Format a UTC offset in seconds as an iCalendar UTC-OFFSET, e.g. `+0100`
+fn format_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.unsigned_abs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if seconds == 0 {
+        format!("{}{:02}{:02}", sign, hours, minutes)
+    } else {
+        format!("{}{:02}{:02}{:02}", sign, hours, minutes, seconds)
+    }
+}
+
+/// Write a VTIMEZONE component that describes the UTC offsets of a time zone from the start of
+/// `first_year` onwards. The changes that keep happening in the same way every year are written
+/// as recurrence rules, so that clients know the offsets after the last year that is checked
+fn write_time_zone(writer: &mut CalendarWriter, time_zone: Tz, first_year: i32, last_year: i32) {
+    let year_start = |year: i32| {
+        NaiveDate::from_ymd_opt(year, 1, 1)
+            .map(|date| date.and_time(NaiveTime::MIN).and_utc().timestamp())
+            .unwrap_or_default()
+    };
+    let (from, to) = (year_start(first_year), year_start(last_year + 1));
+    let Some(first_offset) = zone_offset(time_zone, from) else {
+        return;
+    };
+
+    // Group the changes into the series that happen every year
+    let mut series: Vec<OffsetChangeSeries> = vec![];
+    for change in offset_changes(time_zone, from, to) {
+        // The changes are described in the local time before them
+        let Some(local_time) = DateTime::from_timestamp(change.time, 0)
+            .map(|time| time.naive_utc() + chrono::Duration::seconds(change.from.seconds.into()))
+        else {
+            continue;
+        };
+        let date = local_time.date();
+        let days_in_month = date
+            .with_day(1)
+            .and_then(|first_day| first_day.checked_add_months(Months::new(1)))
+            .and_then(|next_month| next_month.pred_opt())
+            .map(|last_day| last_day.day())
+            .unwrap_or(31);
+        // The changes usually happen on the last or on the nth day of the week in a month
+        let week = if date.day() + 7 > days_in_month {
+            -1
+        } else {
+            ((date.day() - 1) / 7 + 1) as i32
+        };
+        let rule = (
+            date.month(),
+            week,
+            weekday_code(date.weekday().into()),
+            local_time.time(),
+        );
+
+        match series.iter_mut().find(|series| {
+            series.from == change.from
+                && series.to == change.to
+                && series.rule == rule
+                && series.last_year + 1 == date.year()
+        }) {
+            Some(series) => {
+                series.last_year = date.year();
+                series.last_time = change.time;
+                series.count += 1;
+            }
+            None => series.push(OffsetChangeSeries {
+                from: change.from,
+                to: change.to,
+                first_local_time: local_time,
+                rule,
+                last_year: date.year(),
+                last_time: change.time,
+                count: 1,
+            }),
+        }
+    }
+
+    let write_observance = |writer: &mut CalendarWriter,
+                            from: &ZoneOffset,
+                            to: &ZoneOffset,
+                            start: NaiveDateTime,
+                            rule: Option<String>| {
+        let component = if to.is_daylight {
+            "DAYLIGHT"
+        } else {
+            "STANDARD"
+        };
+        writer.line(&format!("BEGIN:{}", component));
+        writer.line(&format!("DTSTART:{}", start.format("%Y%m%dT%H%M%S")));
+        writer.line(&format!("TZOFFSETFROM:{}", format_offset(from.seconds)));
+        writer.line(&format!("TZOFFSETTO:{}", format_offset(to.seconds)));
+        writer.line(&format!("TZNAME:{}", escape_text(&to.name)));
+        if let Some(rule) = rule {
+            writer.line(&format!("RRULE:{}", rule));
+        }
+        writer.line(&format!("END:{}", component));
+    };
+
+    writer.line("BEGIN:VTIMEZONE");
+    writer.line(&format!("TZID:{}", time_zone.name()));
+    writer.line(&format!("X-LIC-LOCATION:{}", time_zone.name()));
+    // The offset at the start, so that the earlier times have one as well
+    let start = DateTime::from_timestamp(from + i64::from(first_offset.seconds), 0)
+        .unwrap_or_default()
+        .naive_utc();
+    write_observance(writer, &first_offset, &first_offset, start, None);
+    for series in &series {
+        let (month, week, weekday, _) = series.rule;
+        let rule = (series.count > 1).then(|| {
+            let rule = format!("FREQ=YEARLY;BYMONTH={};BYDAY={}{}", month, week, weekday);
+            // The series that are still going on carry on after the last year that was checked
+            if series.last_year == last_year {
+                rule
+            } else {
+                format!("{};UNTIL={}", rule, format_time(series.last_time))
+            }
+        });
+        write_observance(
+            writer,
+            &series.from,
+            &series.to,
+            series.first_local_time,
+            rule,
+        );
+    }
+    writer.line("END:VTIMEZONE");
+}
+
+/// Get the VTIMEZONE component of a time zone for the years from `first_year` to `last_year`.
+/// Finding the changes of the offsets takes a while, and the same components are exported each
+/// time a calendar feed is fetched, so they are kept
+fn time_zone_text(time_zone: Tz, first_year: i32, last_year: i32) -> String {
+    type Cache = Mutex<HashMap<(Tz, i32, i32), String>>;
+    static CACHE: OnceLock<Cache> = OnceLock::new();
+    let cache = CACHE.get_or_init(Default::default);
+    let key = (time_zone, first_year, last_year);
+
+    if let Some(text) = cache.lock().ok().and_then(|cache| cache.get(&key).cloned()) {
+        return text;
+    }
+
+    let mut writer = CalendarWriter {
+        text: String::new(),
+    };
+    write_time_zone(&mut writer, time_zone, first_year, last_year);
+
+    if let Ok(mut cache) = cache.lock() {
+        // Start again rather than keeping every range that was ever asked for
+        if cache.len() >= MAX_CACHED_TIME_ZONES {
+            cache.clear();
+        }
+        cache.insert(key, writer.text.clone());
+    }
+    writer.text
+}
+
+/// Build an iCalendar (RFC 5545) calendar with a VEVENT for each event.
+/// `groups` are the groups the events belong to, and `exceptions` are the changes to their
+/// occurrences. `domain` is the domain of the server, which makes the ids of the events unique
+pub fn export_calendar(
+    name: &str,
+    domain: &str,
+    events: &[Event],
+    groups: &[Group],
+    exceptions: &[EventException],
+) -> String {
+    let mut writer = CalendarWriter {
+        text: String::new(),
+    };
+    let now = format_time(Utc::now().timestamp());
+
+    writer.line("BEGIN:VCALENDAR");
+    writer.line("VERSION:2.0");
+    writer.line("PRODID:-//A-level coursework//Calendar//EN");
+    writer.line("CALSCALE:GREGORIAN");
+    writer.line("METHOD:PUBLISH");
+    writer.line(&format!("X-WR-CALNAME:{}", escape_text(name)));
+
+    // Describe the time zones the times of the events are written in, for the years the events
+    // happen in
+    let this_year = Utc::now().year();
+    let mut time_zones: Vec<(Tz, i32, i32)> = vec![];
+    for event in events.iter().filter(|event| event.time_zone() != Tz::UTC) {
+        let time_zone = event.time_zone();
+        let year_of = |timestamp: i64| {
+            to_local_time(timestamp, time_zone)
+                .map(|time| time.year())
+                .unwrap_or(this_year)
+                .clamp(FIRST_TIME_ZONE_YEAR, this_year + 1)
+        };
+        let rule = event.recurrence_rule();
+        let last_time = match (rule.frequency, rule.until) {
+            (Frequency::Once, _) => event.start_time.saturating_add(event.duration),
+            (_, Some(until)) => until,
+            // The recurrence rules of the time zones carry on after the last year
+            (_, None) => i64::MAX,
+        };
+        // The moved occurrences are written in the time zone of the event as well
+        let (first_year, last_year) = exceptions
+            .iter()
+            .filter(|exception| exception.event_id == event.id)
+            .filter_map(|exception| exception.rescheduled_time(event))
+            .fold(
+                (year_of(event.start_time), year_of(last_time)),
+                |(first_year, last_year), (start_time, _)| {
+                    let year = year_of(start_time);
+                    (first_year.min(year), last_year.max(year))
+                },
+            );
+
+        match time_zones
+            .iter_mut()
+            .find(|(other_time_zone, _, _)| *other_time_zone == time_zone)
+        {
+            Some((_, first, last)) => {
+                *first = (*first).min(first_year);
+                *last = (*last).max(last_year);
+            }
+            None => time_zones.push((time_zone, first_year, last_year)),
+        }
+    }
+    for (time_zone, first_year, last_year) in time_zones {
+        writer
+            .text
+            .push_str(&time_zone_text(time_zone, first_year, last_year));
+    }
+
+    for event in events {
+        let group_name = groups
+            .iter()
+            .find(|group| group.id == event.group_id)
+            .map(|group| group.name.as_str())
+            .unwrap_or_default();
+        let uid = format!("event-{}@{}", event.id, domain);
+        let time_zone = event.time_zone();
+        let event_exceptions: Vec<_> = exceptions
+            .iter()
+            .filter(|exception| {
+                exception.event_id == event.id
+                    && is_occurrence(event, exception.original_start_time)
+            })
+            .collect();
+
+        // Write the properties shared by the event and its changed occurrences
+        let write_details = |writer: &mut CalendarWriter, start_time: i64, duration: i64| {
+            writer.line(&format!("UID:{}", uid));
+            writer.line(&format!("DTSTAMP:{}", now));
+            writer.line(&time_property("DTSTART", start_time, time_zone));
+            writer.line(&format!("DURATION:PT{}S", duration));
+            writer.line(&format!("SUMMARY:{}", escape_text(&event.title)));
+            writer.line(&format!("DESCRIPTION:Group: {}", escape_text(group_name)));
+            writer.line(&format!("CATEGORIES:{}", escape_text(group_name)));
+            writer.line(match event.visibility {
+                VisibilityType::Public => "CLASS:PUBLIC",
+                VisibilityType::Private => "CLASS:PRIVATE",
+            });
+        };
+
+        writer.line("BEGIN:VEVENT");
+        write_details(&mut writer, event.start_time, event.duration);
+        if let Some(rule) = recurrence_rule_value(&event.recurrence_rule()) {
+            writer.line(&format!("RRULE:{}", rule));
+        }
+        // Leave out the occurrences that were cancelled. The moved ones are overridden below
+        for exception in event_exceptions
+            .iter()
+            .filter(|exception| exception.is_cancelled)
+        {
+            writer.line(&time_property(
+                "EXDATE",
+                exception.original_start_time,
+                time_zone,
+            ));
+        }
+        writer.line("END:VEVENT");
+
+        // Add the occurrences that were moved as separate components
+        for exception in &event_exceptions {
+            if let Some((start_time, duration)) = exception.rescheduled_time(event) {
+                writer.line("BEGIN:VEVENT");
+                write_details(&mut writer, start_time, duration);
+                writer.line(&time_property(
+                    "RECURRENCE-ID",
+                    exception.original_start_time,
+                    time_zone,
+                ));
+                writer.line("END:VEVENT");
+            }
+        }
+    }
+
+    writer.line("END:VCALENDAR");
+
+    writer.text
+}
+
+/// A content line of an iCalendar object, e.g. `DTSTART;TZID=Europe/London:20230109T090000`
+struct ContentLine {
+    /// The name of the property, in upper case
+    name: String,
+    /// The parameters of the property, with the names in upper case
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl ContentLine {
+    /// Parse a single (unfolded) content line
+    fn parse(line: &str) -> Option<Self> {
+        // Split the line at the first colon that is not in a quoted parameter value
+        let mut in_quotes = false;
+        let colon = line.char_indices().find(|(_, character)| {
+            if *character == '"' {
+                in_quotes = !in_quotes;
+            }
+            *character == ':' && !in_quotes
+        })?;
+        let (name_and_params, value) = (&line[..colon.0], &line[colon.0 + 1..]);
+
+        let mut parts = name_and_params.split(';');
+        let name = parts.next()?.trim().to_uppercase();
+        let params = parts
+            .filter_map(|param| {
+                let (key, value) = param.split_once('=')?;
+                Some((key.to_uppercase(), value.trim_matches('"').to_string()))
+            })
+            .collect();
+
+        Some(ContentLine {
+            name,
+            params,
+            value: value.to_string(),
+        })
+    }
+
+    /// Get the value of a parameter
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parse a DATE or DATE-TIME `value` of this property, which may have several values.
+    /// Times without a TZID or a UTC designator are in `default_time_zone`
+    fn parse_time(&self, value: &str, default_time_zone: Tz) -> Result<(i64, bool, Tz), String> {
+        parse_time(&self.name, value, self.param("TZID"), default_time_zone)
+    }
+}
+
+/// A component of a calendar that could not be imported, and why
+#[derive(Serialize, Debug)]
+pub struct SkippedComponent {
+    /// The type of the component, e.g. VEVENT or VTODO
+    pub component: String,
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub reason: String,
+}
+
+/// An event read from a calendar
+pub struct ImportedEvent {
+    pub event: UnsavedEvent,
+    /// The start times of the occurrences that were excluded with EXDATE
+    pub excluded_start_times: Vec<i64>,
+}
+
+/// The result of reading a calendar
+pub struct ImportedCalendar {
+    pub events: Vec<ImportedEvent>,
+    pub skipped: Vec<SkippedComponent>,
+}
+
+/// Parse a DATE or DATE-TIME value of the property `name` into a UNIX timestamp in seconds,
+/// whether it is a date, and the time zone it is in. `time_zone` is the TZID parameter of the
+/// property, and dates and floating times are in `default_time_zone`
+fn parse_time(
+    name: &str,
+    value: &str,
+    time_zone: Option<&str>,
+    default_time_zone: Tz,
+) -> Result<(i64, bool, Tz), String> {
+    let is_date = value.len() == 8;
+    let (time, time_zone) = if is_date {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map_err(|_| format!("{} has an invalid date", name))?;
+        (date.and_time(NaiveTime::MIN), default_time_zone)
+    } else {
+        let (value, time_zone) = match (value.strip_suffix('Z'), time_zone) {
+            (Some(value), _) => (value, Tz::UTC),
+            (None, Some(time_zone)) => (
+                value,
+                time_zone
+                    .parse()
+                    .map_err(|_| format!("{} is in an unknown time zone {}", name, time_zone))?,
+            ),
+            (None, None) => (value, default_time_zone),
+        };
+        let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .map_err(|_| format!("{} has an invalid time", name))?;
+        (time, time_zone)
+    };
+
+    let time = to_timestamp(time, time_zone).ok_or_else(|| format!("{} is out of range", name))?;
+    Ok((time, is_date, time_zone))
+}
+
+/// Parse a DURATION value, such as `PT1H30M`, into a number of seconds
+fn parse_duration(value: &str) -> Result<i64, String> {
+    let invalid = || format!("The duration {} is invalid", value);
+
+    let value = value.strip_prefix('+').unwrap_or(value);
+    if value.starts_with('-') {
+        return Err("Negative durations are not supported".to_string());
+    }
+    let value = value.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut seconds: i64 = 0;
+    let mut number = String::new();
+    let mut is_time = false;
+    for character in value.chars() {
+        if character.is_ascii_digit() {
+            number.push(character);
+            continue;
+        }
+        if character == 'T' {
+            is_time = true;
+            continue;
+        }
+
+        let amount: i64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+        let unit = match (character, is_time) {
+            ('W', false) => 7 * 24 * 60 * 60,
+            ('D', false) => 24 * 60 * 60,
+            ('H', true) => 60 * 60,
+            ('M', true) => 60,
+            ('S', true) => 1,
+            _ => return Err(invalid()),
+        };
+        seconds = amount
+            .checked_mul(unit)
+            .and_then(|amount| seconds.checked_add(amount))
+            .ok_or_else(invalid)?;
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(seconds)
+}
+
+/// Parse an RRULE value into a recurrence rule
+fn parse_recurrence_rule(value: &str, time_zone: Tz) -> Result<RecurrenceRule, String> {
+    let mut rule = RecurrenceRule::once();
+    let mut has_frequency = false;
+
+    for part in value.split(';').filter(|part| !part.is_empty()) {
+        let (key, part_value) = part
+            .split_once('=')
+            .ok_or_else(|| format!("The recurrence rule part {} is invalid", part))?;
+        let invalid = || format!("The recurrence rule part {} is invalid", part);
+
+        match key.to_uppercase().as_str() {
+            "FREQ" => {
+                has_frequency = true;
+                rule.frequency = match part_value {
+                    "DAILY" => Frequency::Daily,
+                    "WEEKLY" => Frequency::Weekly,
+                    "MONTHLY" => Frequency::Monthly,
+                    "YEARLY" => Frequency::Yearly,
+                    _ => return Err(format!("The frequency {} is not supported", part_value)),
+                };
+            }
+            "INTERVAL" => rule.interval = part_value.parse().map_err(|_| invalid())?,
+            "COUNT" => rule.count = Some(part_value.parse().map_err(|_| invalid())?),
+            "UNTIL" => {
+                let (until, is_date, _) = parse_time("UNTIL", part_value, None, time_zone)?;
+                // A date includes the whole of that day
+                rule.until = Some(if is_date {
+                    until.saturating_add(24 * 60 * 60 - 1)
+                } else {
+                    until
+                });
+            }
+            "BYDAY" => {
+                rule.by_day = part_value
+                    .split(',')
+                    .map(|code| {
+                        weekday_from_code(code).ok_or_else(|| {
+                            format!("Repeating on the day {} is not supported", code)
+                        })
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            // Weeks starting on Monday are the only ones supported
+            "WKST" if part_value == "MO" => {}
+            key => return Err(format!("The recurrence rule part {} is not supported", key)),
+        }
+    }
+
+    if has_frequency {
+        Ok(rule)
+    } else {
+        Err("The recurrence rule has no frequency".to_string())
+    }
+}
+
+/// Convert the properties of a VEVENT into an event in the group with `group_id`. Times without a
+/// time zone are in `default_time_zone`
+fn convert_event(
+    lines: &[ContentLine],
+    group_id: i32,
+    default_time_zone: Tz,
+) -> Result<ImportedEvent, String> {
+    let find = |name: &str| lines.iter().find(|line| line.name == name);
+
+    for unsupported in ["RECURRENCE-ID", "RDATE"] {
+        if find(unsupported).is_some() {
+            return Err(format!("{} is not supported", unsupported));
+        }
+    }
+    if lines.iter().filter(|line| line.name == "RRULE").count() > 1 {
+        return Err("Events with several recurrence rules are not supported".to_string());
+    }
+
+    let start_line = find("DTSTART").ok_or("The event has no start time")?;
+    // The event repeats in the time zone of its start
+    let (start_time, is_date, time_zone) =
+        start_line.parse_time(&start_line.value, default_time_zone)?;
+
+    let duration = if let Some(line) = find("DURATION") {
+        parse_duration(&line.value)?
+    } else if let Some(line) = find("DTEND") {
+        let (end_time, _, _) = line.parse_time(&line.value, time_zone)?;
+        end_time - start_time
+    } else if is_date {
+        // An event on a date lasts for the whole day
+        24 * 60 * 60
+    } else {
+        0
+    };
+    if duration <= 0 {
+        return Err("The event has to last for some time".to_string());
+    }
+
+    let recurrence = match find("RRULE") {
+        Some(line) => parse_recurrence_rule(&line.value, time_zone)?,
+        None => RecurrenceRule::once(),
+    };
+    recurrence.validate(start_time, time_zone)?;
+
+    let mut excluded_start_times = vec![];
+    for line in lines.iter().filter(|line| line.name == "EXDATE") {
+        for value in line.value.split(',') {
+            excluded_start_times.push(line.parse_time(value, time_zone)?.0);
+        }
+    }
+
+    let title: String = find("SUMMARY")
+        .map(|line| unescape_text(&line.value))
+        .unwrap_or_default()
+        .chars()
+        .take(MAX_EVENT_TITLE_LENGTH)
+        .collect();
+    let visibility = match find("CLASS").map(|line| line.value.as_str()) {
+        Some("PUBLIC") => VisibilityType::Public,
+        _ => VisibilityType::Private,
+    };
+
+    Ok(ImportedEvent {
+        event: UnsavedEvent {
+            title: if title.is_empty() {
+                "Untitled".to_string()
+            } else {
+                title
+            },
+            visibility,
+            start_time,
+            duration,
+            group_id,
+            recurrence,
+            time_zone: time_zone.name().to_string(),
+        },
+        excluded_start_times,
+    })
+}
+
+/// Read the events of an iCalendar (RFC 5545) calendar so that they can be added to the group
+/// with `group_id`. Times without a time zone are in `default_time_zone`. The components that can
+/// not be imported are reported as skipped
+pub fn import_calendar(
+    text: &str,
+    group_id: i32,
+    default_time_zone: Tz,
+) -> Result<ImportedCalendar, &'static str> {
+    // Unfold the lines that were split because of their length
+    let mut unfolded_lines: Vec<String> = vec![];
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), unfolded_lines.last_mut()) {
+            (Some(continuation), Some(last_line)) => last_line.push_str(continuation),
+            _ => unfolded_lines.push(line.to_string()),
+        }
+    }
+
+    let mut has_calendar = false;
+    // The names of the components that the current line is in
+    let mut nesting: Vec<String> = vec![];
+    // The components directly in the calendar, with their properties
+    let mut components: Vec<(String, Vec<ContentLine>)> = vec![];
+
+    for line in unfolded_lines
+        .iter()
+        .filter_map(|line| ContentLine::parse(line))
+    {
+        match line.name.as_str() {
+            "BEGIN" => {
+                let component = line.value.to_uppercase();
+                has_calendar |= component == "VCALENDAR";
+                nesting.push(component);
+                if nesting.len() == 2 {
+                    components.push((nesting[1].clone(), vec![]));
+                }
+            }
+            "END" => {
+                nesting.pop();
+            }
+            // Only the properties of the components directly in the calendar are used
+            _ if nesting.len() == 2 => {
+                if let Some((_, properties)) = components.last_mut() {
+                    properties.push(line);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Time zones with their own TZID, e.g. the ones of Outlook, may say which IANA time zone they
+    // are in X-LIC-LOCATION
+    let locations: Vec<(String, String)> = components
+        .iter()
+        .filter(|(component, _)| component == "VTIMEZONE")
+        .filter_map(|(_, properties)| {
+            let find = |name: &str| {
+                properties
+                    .iter()
+                    .find(|line| line.name == name)
+                    .map(|line| line.value.clone())
+            };
+            Some((find("TZID")?, find("X-LIC-LOCATION")?))
+        })
+        .collect();
+
+    let mut calendar = ImportedCalendar {
+        events: vec![],
+        skipped: vec![],
+    };
+    for (component, mut properties) in components {
+        // Refer to the time zones by the IANA names that they are in
+        for line in &mut properties {
+            for (key, value) in &mut line.params {
+                if key != "TZID" || value.parse::<Tz>().is_ok() {
+                    continue;
+                }
+                if let Some((_, location)) = locations.iter().find(|(id, _)| id == value) {
+                    *value = location.clone();
+                }
+            }
+        }
+
+        let result = match component.as_str() {
+            "VEVENT" => Some(convert_event(&properties, group_id, default_time_zone)),
+            // Time zones only describe the times of other components
+            "VTIMEZONE" => None,
+            _ => Some(Err("Only events can be imported".to_string())),
+        };
+        let find = |name: &str| {
+            properties
+                .iter()
+                .find(|line| line.name == name)
+                .map(|line| unescape_text(&line.value))
+        };
+        match result {
+            Some(Ok(event)) => calendar.events.push(event),
+            None => {}
+            Some(Err(reason)) => calendar.skipped.push(SkippedComponent {
+                uid: find("UID"),
+                summary: find("SUMMARY"),
+                component,
+                reason,
+            }),
+        }
+    }
+
+    if has_calendar {
+        Ok(calendar)
+    } else {
+        Err("This is not an iCalendar file")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Get the UNIX timestamp of a wall-clock time such as `2023-01-09 09:00` in a time zone
+    fn timestamp(time: &str, time_zone: Tz) -> i64 {
+        let time = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap();
+        to_timestamp(time, time_zone).unwrap()
+    }
+
+    fn event(id: i32, title: &str, start: &str, time_zone: Tz, rule: RecurrenceRule) -> Event {
+        Event {
+            id,
+            title: title.to_string(),
+            visibility: VisibilityType::Public,
+            start_time: timestamp(start, time_zone),
+            duration: 90 * 60,
+            group_id: 1,
+            recurrence_frequency: rule.frequency,
+            recurrence_interval: rule.interval,
+            recurrence_by_day: rule.by_day,
+            recurrence_until: rule.until,
+            recurrence_count: rule.count,
+            time_zone: time_zone.name().to_string(),
+        }
+    }
+
+    fn group() -> Group {
+        Group {
+            id: 1,
+            is_special: false,
+            name: String::from("Club"),
+            owner_id: 1,
+        }
+    }
+
+    /// Wrap the lines of components in a calendar
+    fn calendar(lines: &[&str]) -> String {
+        let mut text = String::from("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n");
+        for line in lines {
+            text.push_str(line);
+            text.push_str("\r\n");
+        }
+        text.push_str("END:VCALENDAR\r\n");
+        text
+    }
+
+    fn import(text: &str) -> ImportedCalendar {
+        import_calendar(text, 1, Tz::UTC).unwrap()
+    }
+
+    #[test]
+    fn round_trips_events_through_export_and_import() {
+        let london = Tz::Europe__London;
+        let events = [
+            event(
+                1,
+                "Practice; bring water, shoes\\socks",
+                "2023-01-09 09:00",
+                london,
+                RecurrenceRule {
+                    frequency: Frequency::Weekly,
+                    interval: 2,
+                    by_day: vec![Weekday::Monday, Weekday::Thursday],
+                    until: Some(timestamp("2023-12-31 00:00", Tz::UTC)),
+                    count: None,
+                },
+            ),
+            event(
+                2,
+                "Call",
+                "2023-07-01 16:30",
+                Tz::America__New_York,
+                RecurrenceRule::once(),
+            ),
+            event(
+                3,
+                "Standup",
+                "2023-01-02 08:00",
+                Tz::UTC,
+                RecurrenceRule {
+                    frequency: Frequency::Daily,
+                    count: Some(5),
+                    ..RecurrenceRule::once()
+                },
+            ),
+        ];
+        let exception = |id, original: &str, new_start: Option<&str>| EventException {
+            id,
+            event_id: 1,
+            original_start_time: timestamp(original, london),
+            is_cancelled: new_start.is_none(),
+            new_start_time: new_start.map(|time| timestamp(time, london)),
+            new_duration: None,
+        };
+        let exceptions = [
+            // During summer time
+            exception(1, "2023-07-10 09:00", None),
+            exception(2, "2023-01-26 09:00", Some("2023-01-27 10:00")),
+        ];
+
+        let text = export_calendar("Calendar", "example.com", &events, &[group()], &exceptions);
+        let imported = import(&text);
+
+        assert_eq!(imported.events.len(), events.len());
+        for (event, imported) in events.iter().zip(&imported.events) {
+            let imported = &imported.event;
+            assert_eq!(imported.title, event.title);
+            assert_eq!(imported.visibility, event.visibility);
+            assert_eq!(imported.start_time, event.start_time);
+            assert_eq!(imported.duration, event.duration);
+            assert_eq!(imported.recurrence, event.recurrence_rule());
+            assert_eq!(imported.time_zone, event.time_zone);
+        }
+        assert_eq!(
+            imported.events[0].excluded_start_times,
+            [timestamp("2023-07-10 09:00", london)]
+        );
+        // Moved occurrences can not be imported, but they are reported
+        assert_eq!(imported.skipped.len(), 1);
+        assert_eq!(
+            imported.skipped[0].uid.as_deref(),
+            Some("event-1@example.com")
+        );
+        assert_eq!(imported.skipped[0].reason, "RECURRENCE-ID is not supported");
+    }
+
+    #[test]
+    fn exports_a_time_zone_for_every_tzid() {
+        let events = [
+            event(
+                1,
+                "A",
+                "2023-01-09 09:00",
+                Tz::Europe__London,
+                RecurrenceRule::once(),
+            ),
+            event(
+                2,
+                "B",
+                "2022-05-01 09:00",
+                Tz::Europe__London,
+                RecurrenceRule::once(),
+            ),
+            event(3, "C", "2023-01-09 09:00", Tz::UTC, RecurrenceRule::once()),
+        ];
+        let text = export_calendar("Calendar", "example.com", &events, &[group()], &[]);
+
+        assert_eq!(text.matches("BEGIN:VTIMEZONE").count(), 1);
+        assert!(text.contains("TZID:Europe/London\r\n"));
+        // The time zone is described from the year of the first event in it
+        assert!(text.contains("DTSTART:20220101T000000\r\n"));
+        assert!(text.contains("DTSTART;TZID=Europe/London:20230109T090000\r\n"));
+        assert!(text.contains("DTSTART:20230109T090000Z\r\n"));
+    }
+
+    #[test]
+    fn only_describes_the_years_of_the_events() {
+        let london = Tz::Europe__London;
+        let mut events = [event(
+            1,
+            "A",
+            "2005-06-01 09:00",
+            london,
+            RecurrenceRule::once(),
+        )];
+        let text = export_calendar("Calendar", "example.com", &events, &[group()], &[]);
+
+        assert!(text.contains("DTSTART:20050101T000000\r\n"));
+        assert!(text.contains("DTSTART:20050327T010000\r\n"));
+        assert!(!text.contains("DTSTART:2006"));
+        assert!(!text.contains("RRULE:FREQ=YEARLY"));
+
+        // Events that repeat forever need the changes until the next year
+        events[0].recurrence_frequency = Frequency::Yearly;
+        let text = export_calendar("Calendar", "example.com", &events, &[group()], &[]);
+        assert!(text.contains("RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU\r\n"));
+        assert_eq!(
+            time_zone_text(london, 2005, 2030),
+            time_zone_text(london, 2005, 2030)
+        );
+    }
+
+    #[test]
+    fn describes_the_yearly_offset_changes_with_rules() {
+        let mut writer = CalendarWriter {
+            text: String::new(),
+        };
+        write_time_zone(&mut writer, Tz::America__New_York, 2010, 2030);
+
+        let expected = [
+            "BEGIN:VTIMEZONE",
+            "TZID:America/New_York",
+            "X-LIC-LOCATION:America/New_York",
+            "BEGIN:STANDARD",
+            "DTSTART:20091231T190000",
+            "TZOFFSETFROM:-0500",
+            "TZOFFSETTO:-0500",
+            "TZNAME:EST",
+            "END:STANDARD",
+            "BEGIN:DAYLIGHT",
+            "DTSTART:20100314T020000",
+            "TZOFFSETFROM:-0500",
+            "TZOFFSETTO:-0400",
+            "TZNAME:EDT",
+            "RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=2SU",
+            "END:DAYLIGHT",
+            "BEGIN:STANDARD",
+            "DTSTART:20101107T020000",
+            "TZOFFSETFROM:-0400",
+            "TZOFFSETTO:-0500",
+            "TZNAME:EST",
+            "RRULE:FREQ=YEARLY;BYMONTH=11;BYDAY=1SU",
+            "END:STANDARD",
+            "END:VTIMEZONE",
+            "",
+        ];
+        assert_eq!(writer.text, expected.join("\r\n"));
+    }
+
+    #[test]
+    fn ends_the_rules_of_offset_changes_that_stopped() {
+        let mut writer = CalendarWriter {
+            text: String::new(),
+        };
+        // Moscow stopped changing its clocks in 2011
+        write_time_zone(&mut writer, Tz::Europe__Moscow, 2008, 2030);
+
+        assert!(writer
+            .text
+            .contains("RRULE:FREQ=YEARLY;BYMONTH=3;BYDAY=-1SU;UNTIL=20100327T230000Z\r\n"));
+        assert!(writer
+            .text
+            .contains("DTSTART:20110327T020000\r\nTZOFFSETFROM:+0300\r\nTZOFFSETTO:+0400\r\n"));
+    }
+
+    #[test]
+    fn formats_utc_offsets() {
+        assert_eq!(format_offset(0), "+0000");
+        assert_eq!(format_offset(-5 * 60 * 60), "-0500");
+        assert_eq!(format_offset(5 * 60 * 60 + 30 * 60), "+0530");
+        assert_eq!(format_offset(-(60 * 60 + 15 * 60 + 30)), "-011530");
+    }
+
+    #[test]
+    fn folds_long_lines_and_unfolds_them() {
+        // The title is long, and has characters that take several octets
+        let title: String = "Ünïcödé title ".repeat(6).trim().to_string();
+        let events = [event(
+            1,
+            &title,
+            "2023-01-09 09:00",
+            Tz::UTC,
+            RecurrenceRule::once(),
+        )];
+        let text = export_calendar("Calendar", "example.com", &events, &[group()], &[]);
+
+        assert!(text.split("\r\n").all(|line| line.len() <= MAX_LINE_LENGTH));
+        assert!(text.contains("\r\n "));
+        assert_eq!(import(&text).events[0].event.title, title);
+    }
+
+    #[test]
+    fn unfolds_lines_folded_with_tabs() {
+        let text = calendar(&[
+            "BEGIN:VEVENT",
+            "DTSTART:20230109T090000Z",
+            "DURATION:PT1H",
+            "SUMMARY:A long",
+            "\t title",
+            "END:VEVENT",
+        ]);
+
+        assert_eq!(import(&text).events[0].event.title, "A long title");
+    }
+
+    #[test]
+    fn escapes_and_unescapes_text() {
+        let text = "a;b,c\\d\nnew line";
+        assert_eq!(escape_text(text), r"a\;b\,c\\d\nnew line");
+        assert_eq!(unescape_text(&escape_text(text)), text);
+        assert_eq!(unescape_text("upper\\Ncase"), "upper\ncase");
+    }
+
+    #[test]
+    fn parses_content_lines_with_quoted_parameters() {
+        let line =
+            ContentLine::parse("dtstart;tzid=\"Custom: zone\";VALUE=DATE-TIME:20230109T090000")
+                .unwrap();
+
+        assert_eq!(line.name, "DTSTART");
+        assert_eq!(line.param("TZID"), Some("Custom: zone"));
+        assert_eq!(line.param("VALUE"), Some("DATE-TIME"));
+        assert_eq!(line.value, "20230109T090000");
+        assert!(ContentLine::parse("no colon").is_none());
+    }
+
+    #[test]
+    fn parses_utc_zoned_floating_and_date_times() {
+        let london = Tz::Europe__London;
+        let default_time_zone = Tz::America__New_York;
+
+        assert_eq!(
+            parse_time(
+                "DTSTART",
+                "20230709T090000Z",
+                Some("Europe/London"),
+                default_time_zone
+            ),
+            Ok((timestamp("2023-07-09 09:00", Tz::UTC), false, Tz::UTC))
+        );
+        assert_eq!(
+            parse_time(
+                "DTSTART",
+                "20230709T090000",
+                Some("Europe/London"),
+                default_time_zone
+            ),
+            Ok((timestamp("2023-07-09 09:00", london), false, london))
+        );
+        assert_eq!(
+            parse_time("DTSTART", "20230709T090000", None, default_time_zone),
+            Ok((
+                timestamp("2023-07-09 09:00", default_time_zone),
+                false,
+                default_time_zone
+            ))
+        );
+        assert_eq!(
+            parse_time(
+                "DTSTART",
+                "20230709",
+                Some("Europe/London"),
+                default_time_zone
+            ),
+            Ok((
+                timestamp("2023-07-09 00:00", default_time_zone),
+                true,
+                default_time_zone
+            ))
+        );
+        assert!(parse_time("DTSTART", "20230709T090000", Some("Nowhere"), Tz::UTC).is_err());
+        assert!(parse_time("DTSTART", "2023-07-09", None, Tz::UTC).is_err());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("PT1H30M"), Ok(90 * 60));
+        assert_eq!(parse_duration("+P1DT1S"), Ok(24 * 60 * 60 + 1));
+        assert_eq!(parse_duration("P2W"), Ok(14 * 24 * 60 * 60));
+        assert!(parse_duration("-PT1H").is_err());
+        assert!(parse_duration("P1H").is_err());
+        assert!(parse_duration("PT1").is_err());
+    }
+
+    #[test]
+    fn reads_lists_of_excluded_dates() {
+        let text = calendar(&[
+            "BEGIN:VEVENT",
+            "DTSTART;TZID=Europe/London:20230102T090000",
+            "DURATION:PT1H",
+            "RRULE:FREQ=WEEKLY",
+            "EXDATE;TZID=Europe/London:20230109T090000,20230703T090000",
+            "EXDATE:20230710T080000Z",
+            "END:VEVENT",
+        ]);
+        let london = Tz::Europe__London;
+
+        assert_eq!(
+            import(&text).events[0].excluded_start_times,
+            [
+                timestamp("2023-01-09 09:00", london),
+                timestamp("2023-07-03 09:00", london),
+                timestamp("2023-07-10 09:00", london),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_the_end_and_whole_day_events() {
+        let text = calendar(&[
+            "BEGIN:VEVENT",
+            "DTSTART;TZID=Europe/London:20230326T000000",
+            "DTEND;TZID=Europe/London:20230326T030000",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "DTSTART;VALUE=DATE:20230326",
+            "END:VEVENT",
+        ]);
+        let events = import(&text).events;
+
+        // The clocks went forward in the night
+        assert_eq!(events[0].event.duration, 2 * 60 * 60);
+        assert_eq!(events[1].event.duration, 24 * 60 * 60);
+        assert_eq!(events[1].event.title, "Untitled");
+    }
+
+    #[test]
+    fn uses_the_location_of_custom_time_zones() {
+        let text = calendar(&[
+            "BEGIN:VEVENT",
+            "DTSTART;TZID=\"W. Europe Standard Time\":20230301T090000",
+            "DURATION:PT1H",
+            "END:VEVENT",
+            "BEGIN:VTIMEZONE",
+            "TZID:W. Europe Standard Time",
+            "X-LIC-LOCATION:Europe/Berlin",
+            "BEGIN:STANDARD",
+            "DTSTART:16010101T030000",
+            "TZOFFSETFROM:+0200",
+            "TZOFFSETTO:+0100",
+            "END:STANDARD",
+            "END:VTIMEZONE",
+        ]);
+        let imported = import(&text);
+
+        assert!(imported.skipped.is_empty());
+        assert_eq!(imported.events[0].event.time_zone, "Europe/Berlin");
+        assert_eq!(
+            imported.events[0].event.start_time,
+            timestamp("2023-03-01 09:00", Tz::Europe__Berlin)
+        );
+    }
+
+    #[test]
+    fn reports_the_skipped_components() {
+        let text = calendar(&[
+            "BEGIN:VTODO",
+            "UID:todo",
+            "SUMMARY:Shopping",
+            "END:VTODO",
+            "BEGIN:VEVENT",
+            "UID:rdate",
+            "SUMMARY:Escaped\\, summary",
+            "DTSTART:20230109T090000Z",
+            "RDATE:20230110T090000Z",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:zone",
+            "DTSTART;TZID=Nowhere:20230109T090000",
+            "END:VEVENT",
+            "BEGIN:VEVENT",
+            "UID:monthly",
+            "DTSTART:20230109T090000Z",
+            "DURATION:PT1H",
+            "RRULE:FREQ=MONTHLY;BYMONTHDAY=9",
+            "END:VEVENT",
+        ]);
+        let skipped = import(&text).skipped;
+
+        let reasons: Vec<_> = skipped
+            .iter()
+            .map(|skipped| {
+                (
+                    skipped.component.as_str(),
+                    skipped.uid.as_deref().unwrap(),
+                    skipped.reason.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                ("VTODO", "todo", "Only events can be imported"),
+                ("VEVENT", "rdate", "RDATE is not supported"),
+                (
+                    "VEVENT",
+                    "zone",
+                    "DTSTART is in an unknown time zone Nowhere"
+                ),
+                (
+                    "VEVENT",
+                    "monthly",
+                    "The recurrence rule part BYMONTHDAY is not supported"
+                ),
+            ]
+        );
+        assert_eq!(skipped[1].summary.as_deref(), Some("Escaped, summary"));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_calendars() {
+        assert!(import_calendar("BEGIN:VCARD\r\nEND:VCARD\r\n", 1, Tz::UTC).is_err());
+        assert!(import_calendar("", 1, Tz::UTC).is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    schema::{account_lockouts, login_throttles},
+    settings::{
+        LOGIN_BACKOFF_BASE, LOGIN_BACKOFF_MAX, LOGIN_FAILURE_MEMORY, LOGIN_FREE_ATTEMPTS_PER_IP,
+        LOGIN_FREE_ATTEMPTS_PER_USERNAME, LOGIN_LOCKOUT_DURATION, LOGIN_LOCKOUT_FAILURES,
+    },
+};
+
+use super::models::UnsavedModel;
+
+/// The longest an IP address or a username can be in the throttles and the audit log, the length
+/// of their columns
+const MAX_SUBJECT_LENGTH: usize = 100;
+
+/// What the failed logins are counted for
+#[derive(PartialEq, Debug, Clone, Copy, diesel_derive_enum::DbEnum)]
+#[DieselTypePath = "crate::schema::sql_types::ThrottleKind"]
+pub enum ThrottleKind {
+    Ip,
+    Username,
+}
+
+/// A struct that represents the recent failed logins from an IP address or for a username
+#[derive(Identifiable, Queryable, Debug)]
+#[diesel(table_name = login_throttles)]
+pub struct LoginThrottle {
+    pub id: i32,
+    pub kind: ThrottleKind,
+    /// The IP address or the username
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure_at: i64,
+    /// The UNIX timestamp, in seconds, until which logging in is not allowed
+    pub blocked_until: Option<i64>,
+}
+
+/// An entry in the audit log of the accounts that were locked
+#[derive(Identifiable, Queryable, Serialize, Debug)]
+#[diesel(table_name = account_lockouts)]
+pub struct AccountLockout {
+    pub id: i32,
+    pub username: String,
+    /// The address the last failed login came from
+    pub ip: String,
+    pub failures: i32,
+    pub locked_at: i64,
+    pub locked_until: i64,
+}
+
+/// An entry in the audit log that has not been saved to the database yet
+#[derive(Insertable)]
+#[diesel(table_name = account_lockouts)]
+pub struct UnsavedAccountLockout {
+    pub username: String,
+    pub ip: String,
+    pub failures: i32,
+    pub locked_at: i64,
+    pub locked_until: i64,
+}
+
+impl AccountLockout {
+    /// Get the latest entries of the audit log, the newest first
+    pub fn get_latest(
+        connection: &mut PgConnection,
+        limit: i64,
+    ) -> QueryResult<Vec<AccountLockout>> {
+        account_lockouts::table
+            .order(account_lockouts::locked_at.desc())
+            .limit(limit)
+            .load(connection)
+    }
+}
+
+impl UnsavedModel<AccountLockout> for UnsavedAccountLockout {
+    fn save(self, connection: &mut PgConnection) -> QueryResult<AccountLockout> {
+        diesel::insert_into(account_lockouts::dsl::account_lockouts)
+            .values(self)
+            .get_result(connection)
+    }
+}
+
+impl ThrottleKind {
+    /// The number of failed logins that are allowed before the client has to wait
+    fn free_attempts(self) -> i32 {
+        match self {
+            ThrottleKind::Ip => LOGIN_FREE_ATTEMPTS_PER_IP,
+            ThrottleKind::Username => LOGIN_FREE_ATTEMPTS_PER_USERNAME,
+        }
+    }
+
+    /// Get how many seconds the client has to wait after `failures` failed logins.
+    /// The wait doubles with every failure after the free ones
+    fn backoff(self, failures: i32) -> Option<i64> {
+        let extra_failures = failures.saturating_sub(self.free_attempts());
+        if extra_failures <= 0 {
+            return None;
+        }
+
+        // Stop doubling long before it could overflow
+        let doublings = (extra_failures - 1).min(30);
+        Some(
+            LOGIN_BACKOFF_BASE
+                .saturating_mul(1 << doublings)
+                .min(LOGIN_BACKOFF_MAX),
+        )
+    }
+}
+
+impl LoginThrottle {
+    /// Get how many seconds the client has to wait before it can try to log in as `username` from
+    /// `ip`, if it can not try yet
+    pub fn retry_after(
+        connection: &mut PgConnection,
+        ip: &str,
+        username: &str,
+    ) -> QueryResult<Option<i64>> {
+        use crate::schema::login_throttles::dsl::*;
+        let now = Utc::now().timestamp();
+        let (ip, username) = (limit_length(ip), limit_length(username));
+
+        let latest_block: Option<i64> = login_throttles
+            .filter(
+                kind.eq(ThrottleKind::Ip)
+                    .and(subject.eq(ip))
+                    .or(kind.eq(ThrottleKind::Username).and(subject.eq(username))),
+            )
+            .filter(blocked_until.gt(now))
+            .select(diesel::dsl::max(blocked_until))
+            .first(connection)?;
+
+        Ok(latest_block.map(|until| until - now))
+    }
+
+    /// Count a failed login from `ip` for `username`, so that the next attempt has to wait longer.
+    /// The account is locked after too many failures, in which case the entry in the audit log is
+    /// returned
+    pub fn record_failure(
+        connection: &mut PgConnection,
+        ip: &str,
+        username: &str,
+    ) -> QueryResult<Option<AccountLockout>> {
+        use crate::schema::login_throttles::dsl::*;
+        let now = Utc::now().timestamp();
+        let (ip, username) = (limit_length(ip), limit_length(username));
+
+        connection.transaction(|connection| {
+            // Forget the failures that are too old to matter
+            diesel::delete(
+                login_throttles
+                    .filter(last_failure_at.lt(now - LOGIN_FAILURE_MEMORY))
+                    .filter(blocked_until.is_null().or(blocked_until.le(now))),
+            )
+            .execute(connection)?;
+
+            LoginThrottle::add_failure(connection, ThrottleKind::Ip, ip, now)?;
+            let throttle =
+                LoginThrottle::add_failure(connection, ThrottleKind::Username, username, now)?;
+
+            if throttle.failures < LOGIN_LOCKOUT_FAILURES {
+                return Ok(None);
+            }
+
+            // Lock the account, and start counting again once it is unlocked
+            let locked_until = now + LOGIN_LOCKOUT_DURATION;
+            diesel::update(&throttle)
+                .set((failures.eq(0), blocked_until.eq(locked_until)))
+                .execute(connection)?;
+
+            UnsavedAccountLockout {
+                username: username.to_string(),
+                ip: ip.to_string(),
+                failures: throttle.failures,
+                locked_at: now,
+                locked_until,
+            }
+            .save(connection)
+            .map(Some)
+        })
+    }
+
+    /// Add a failure to the count for `throttle_subject`, blocking it for a while if it has failed
+    /// too many times
+    fn add_failure(
+        connection: &mut PgConnection,
+        throttle_kind: ThrottleKind,
+        throttle_subject: &str,
+        now: i64,
+    ) -> QueryResult<LoginThrottle> {
+        use crate::schema::login_throttles::dsl::*;
+
+        // Make sure that there is a row to lock, so that failures at the same time are all counted
+        diesel::insert_into(login_throttles)
+            .values((
+                kind.eq(throttle_kind),
+                subject.eq(throttle_subject),
+                failures.eq(0),
+                last_failure_at.eq(now),
+            ))
+            .on_conflict((kind, subject))
+            .do_nothing()
+            .execute(connection)?;
+
+        let throttle: LoginThrottle = login_throttles
+            .filter(kind.eq(throttle_kind))
+            .filter(subject.eq(throttle_subject))
+            .for_update()
+            .first(connection)?;
+
+        let new_failures = throttle.failures + 1;
+        let new_blocked_until = throttle_kind
+            .backoff(new_failures)
+            .map(|wait| now + wait)
+            // Do not shorten a lockout
+            .max(throttle.blocked_until);
+
+        diesel::update(&throttle)
+            .set((
+                failures.eq(new_failures),
+                last_failure_at.eq(now),
+                blocked_until.eq(new_blocked_until),
+            ))
+            .get_result(connection)
+    }
+
+    /// Forget the failed logins for `username` after it has logged in successfully.
+    /// The failures from the IP address are kept, so that logging in to one account does not
+    /// allow guessing the passwords of others
+    pub fn record_success(connection: &mut PgConnection, username: &str) -> QueryResult<usize> {
+        use crate::schema::login_throttles::dsl::*;
+
+        diesel::delete(
+            login_throttles
+                .filter(kind.eq(ThrottleKind::Username))
+                .filter(subject.eq(username)),
+        )
+        .execute(connection)
+    }
+}
+
+/// Cut an IP address or a username to the length of its column, so that a long value can not
+/// turn a failed login into a database error
+fn limit_length(subject: &str) -> &str {
+    match subject.char_indices().nth(MAX_SUBJECT_LENGTH) {
+        Some((end, _)) => &subject[..end],
+        None => subject,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_not_wait_during_the_free_attempts() {
+        for kind in [ThrottleKind::Ip, ThrottleKind::Username] {
+            for failures in 0..=kind.free_attempts() {
+                assert_eq!(kind.backoff(failures), None, "{kind:?} after {failures}");
+            }
+        }
+    }
+
+    #[test]
+    fn doubles_the_wait_after_the_free_attempts() {
+        let kind = ThrottleKind::Username;
+        let first = kind.free_attempts() + 1;
+
+        assert_eq!(kind.backoff(first), Some(LOGIN_BACKOFF_BASE));
+        assert_eq!(kind.backoff(first + 1), Some(LOGIN_BACKOFF_BASE * 2));
+        assert_eq!(kind.backoff(first + 2), Some(LOGIN_BACKOFF_BASE * 4));
+        assert_eq!(
+            ThrottleKind::Ip.backoff(LOGIN_FREE_ATTEMPTS_PER_IP + 2),
+            Some(LOGIN_BACKOFF_BASE * 2)
+        );
+    }
+
+    #[test]
+    fn caps_the_wait() {
+        let kind = ThrottleKind::Username;
+        let waits: Vec<_> = (0..100)
+            .filter_map(|failures| kind.backoff(failures))
+            .collect();
+
+        assert!(waits.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(waits.iter().all(|wait| *wait <= LOGIN_BACKOFF_MAX));
+        assert_eq!(waits.last(), Some(&LOGIN_BACKOFF_MAX));
+    }
+
+    #[test]
+    fn does_not_overflow_for_many_failures() {
+        for kind in [ThrottleKind::Ip, ThrottleKind::Username] {
+            assert_eq!(kind.backoff(i32::MAX), Some(LOGIN_BACKOFF_MAX));
+            assert_eq!(kind.backoff(i32::MIN), None);
+        }
+    }
+
+    #[test]
+    fn limits_the_length_of_subjects() {
+        assert_eq!(limit_length("192.0.2.1"), "192.0.2.1");
+
+        let long = "ä".repeat(MAX_SUBJECT_LENGTH + 5);
+        assert_eq!(limit_length(&long).chars().count(), MAX_SUBJECT_LENGTH);
+    }
+}
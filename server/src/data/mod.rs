@@ -1,7 +1,14 @@
+pub mod api_tokens;
+pub mod calendar_feeds;
+pub mod event_exceptions;
 pub mod events;
 pub mod friends;
 pub mod group;
+pub mod icalendar;
+pub mod login_throttle;
 pub mod models;
 pub mod notifications;
+pub mod recurrence;
 pub mod session;
+pub mod two_factor;
 pub mod users;
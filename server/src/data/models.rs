@@ -1,5 +1,9 @@
 use diesel::PgConnection;
 use diesel::QueryResult;
+use rand_core::{OsRng, RngCore};
+
+/// The number of random bytes in a secret token
+const TOKEN_BYTES: usize = 32;
 
 /// A trait for structs that represent a value not yet saved to the database
 /// `T` The type of the same data represented when saved
@@ -8,3 +12,11 @@ pub trait UnsavedModel<T> {
     // Take the ownership of the item so that an unsaved item can no longer be used when it has been saved
     fn save(self, connection: &mut PgConnection) -> QueryResult<T>;
 }
+
+/// Generate a random secret token, e.g. for a calendar feed or a session.
+/// It is encoded as hex so that it can be used in a URL or a cookie
+pub fn random_token() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
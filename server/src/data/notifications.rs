@@ -1,5 +1,9 @@
+use super::friends::Friendship;
 use super::group::ParticipationType;
-use super::{group::Group, users::User};
+use super::{
+    group::Group,
+    users::{User, UserPublic},
+};
 use diesel::BoolExpressionMethods;
 use diesel::RunQueryDsl;
 
@@ -16,6 +20,8 @@ pub struct NotificaitonVec(pub Vec<Notification>);
 #[derive(Serialize)]
 pub enum Notification {
     Invitation(Group),
+    /// A friend request from this user, which has not been answered yet
+    FriendRequest(UserPublic),
 }
 
 impl Notification {
@@ -36,11 +42,19 @@ impl Notification {
             .load::<Group>(connection)?;
 
         // express the events as notifications
-        let notifications: Vec<_> = events_without_response
+        let mut notifications: Vec<_> = events_without_response
             .into_iter()
             .map(|event| Notification::Invitation(event))
             .collect();
 
+        // Add the friend requests the user has to reply to
+        let friend_requests = Friendship::get_incoming_requests(connection, user)?;
+        notifications.extend(
+            friend_requests
+                .into_iter()
+                .map(|requester| Notification::FriendRequest(requester.to_public())),
+        );
+
         Ok(notifications)
     }
 }
@@ -0,0 +1,830 @@
+use chrono::{
+    DateTime, Datelike, Days, LocalResult, Months, NaiveDate, NaiveDateTime, Offset, TimeZone,
+};
+use chrono_tz::Tz;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::schema::events;
+
+use super::{
+    event_exceptions::EventException,
+    events::{Event, VisibilityType},
+};
+
+/// The largest number of periods allowed between the repetitions of an event
+const MAX_INTERVAL: i32 = 1000;
+
+/// How often an event repeats, as in the FREQ part of an RFC 5545 recurrence rule
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, diesel_derive_enum::DbEnum)]
+#[DieselTypePath = "crate::schema::sql_types::RecurrenceFrequency"]
+pub enum Frequency {
+    Once,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A day of the week, used in the BYDAY part of a recurrence rule
+#[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy, diesel_derive_enum::DbEnum)]
+#[DieselTypePath = "crate::schema::sql_types::Weekday"]
+pub enum Weekday {
+    Monday,
+    Tuesday,
+    Wednesday,
+    Thursday,
+    Friday,
+    Saturday,
+    Sunday,
+}
+
+impl From<chrono::Weekday> for Weekday {
+    fn from(weekday: chrono::Weekday) -> Self {
+        match weekday {
+            chrono::Weekday::Mon => Weekday::Monday,
+            chrono::Weekday::Tue => Weekday::Tuesday,
+            chrono::Weekday::Wed => Weekday::Wednesday,
+            chrono::Weekday::Thu => Weekday::Thursday,
+            chrono::Weekday::Fri => Weekday::Friday,
+            chrono::Weekday::Sat => Weekday::Saturday,
+            chrono::Weekday::Sun => Weekday::Sunday,
+        }
+    }
+}
+
+fn default_interval() -> i32 {
+    1
+}
+
+/// A rule that describes when an event repeats, modelled on the RRULE property of RFC 5545.
+/// The first occurrence of the event is always at its start time
+#[derive(Insertable, AsChangeset, Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[diesel(table_name = events)]
+#[diesel(treat_none_as_null = true)]
+pub struct RecurrenceRule {
+    #[diesel(column_name = recurrence_frequency)]
+    pub frequency: Frequency,
+    /// The number of periods between the repetitions, e.g. 2 for an event every other week
+    #[diesel(column_name = recurrence_interval)]
+    #[serde(default = "default_interval")]
+    pub interval: i32,
+    /// The days of the week the event happens on. If it is empty, the event happens on the same
+    /// day as its start time
+    #[diesel(column_name = recurrence_by_day)]
+    #[serde(default)]
+    pub by_day: Vec<Weekday>,
+    /// The UNIX timestamp, in seconds, after which the event does not start anymore
+    #[diesel(column_name = recurrence_until)]
+    #[serde(default)]
+    pub until: Option<i64>,
+    /// The number of times the event happens
+    #[diesel(column_name = recurrence_count)]
+    #[serde(default)]
+    pub count: Option<i32>,
+}
+
+/// A struct that represents a single occurrence of an event, i.e. a concrete time at which the
+/// event happens. Recurring events have many occurrences, while other events only have one
+#[derive(Serialize, PartialEq, Debug)]
+pub struct EventOccurrence {
+    /// The id of the event this is an occurrence of
+    pub event_id: i32,
+    pub title: String,
+    pub visibility: VisibilityType,
+    /// The time this occurrence was scheduled for by the recurrence rule. It is used to refer to
+    /// the occurrence even if it was moved
+    pub original_start_time: i64,
+    /// The time this occurrence starts at, as a UNIX timestamp in seconds
+    pub start_time: i64,
+    pub duration: i64,
+    pub recurrence: RecurrenceRule,
+    pub group_id: i32,
+}
+
+impl EventOccurrence {
+    /// Create an occurrence of an event that was scheduled for `original_start_time` and
+    /// actually happens at `start_time` for `duration` seconds
+    fn new(event: &Event, original_start_time: i64, start_time: i64, duration: i64) -> Self {
+        EventOccurrence {
+            event_id: event.id,
+            title: event.title.clone(),
+            visibility: event.visibility.clone(),
+            original_start_time,
+            start_time,
+            duration,
+            recurrence: event.recurrence_rule(),
+            group_id: event.group_id,
+        }
+    }
+}
+
+/// Convert a UNIX timestamp in seconds to the wall-clock date and time in a time zone
+pub fn to_local_time(timestamp: i64, time_zone: Tz) -> Option<NaiveDateTime> {
+    DateTime::from_timestamp(timestamp, 0).map(|time| time.with_timezone(&time_zone).naive_local())
+}
+
+/// Convert a wall-clock date and time in a time zone to a UNIX timestamp in seconds.
+/// Times that happen twice because of a daylight saving change use the earlier one, and times
+/// that are skipped are moved forward by the length of the change
+pub fn to_timestamp(time: NaiveDateTime, time_zone: Tz) -> Option<i64> {
+    match time_zone.from_local_datetime(&time) {
+        LocalResult::Single(time) | LocalResult::Ambiguous(time, _) => Some(time.timestamp()),
+        LocalResult::None => {
+            // Use the offset from before the change
+            let day_before = time.checked_sub_days(Days::new(1))?;
+            let offset = time_zone
+                .from_local_datetime(&day_before)
+                .earliest()?
+                .offset()
+                .fix();
+            Some(time.and_utc().timestamp() - i64::from(offset.local_minus_utc()))
+        }
+    }
+}
+
+impl RecurrenceRule {
+    /// A rule for an event that does not repeat
+    pub fn once() -> Self {
+        RecurrenceRule {
+            frequency: Frequency::Once,
+            interval: 1,
+            by_day: vec![],
+            until: None,
+            count: None,
+        }
+    }
+
+    /// Check that the rule makes sense for an event that starts at `start_time` and repeats in
+    /// `time_zone`
+    pub fn validate(&self, start_time: i64, time_zone: Tz) -> Result<(), &'static str> {
+        let start = to_local_time(start_time, time_zone).ok_or("The start time is out of range")?;
+
+        if self.frequency == Frequency::Once {
+            return if self.interval == 1
+                && self.by_day.is_empty()
+                && self.until.is_none()
+                && self.count.is_none()
+            {
+                Ok(())
+            } else {
+                Err("An event that happens once can not repeat")
+            };
+        }
+
+        if !(1..=MAX_INTERVAL).contains(&self.interval) {
+            return Err("The event has to repeat at least every 1000 periods");
+        }
+        if self.until.is_some() && self.count.is_some() {
+            return Err("The event can not have both an end date and a number of occurrences");
+        }
+        if matches!(self.count, Some(count) if count < 1) {
+            return Err("The event has to happen at least once");
+        }
+        if matches!(self.until, Some(until) if until < start_time) {
+            return Err("The event can not stop repeating before it has begun");
+        }
+        if self.frequency == Frequency::Yearly && !self.by_day.is_empty() {
+            return Err("Yearly events can not be limited to days of the week");
+        }
+        if !self.by_day.is_empty() {
+            // The first occurrence has to be one that the rule generates
+            if !self.by_day.contains(&start.weekday().into()) {
+                return Err("The event has to start on one of the days it repeats on");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the first day of the `period`th period counting from the period that contains `start`
+    /// Returns None if the date is out of range
+    fn period_start(&self, start: NaiveDate, period: u32) -> Option<NaiveDate> {
+        let interval = u32::try_from(self.interval).ok()?;
+        let periods = period.checked_mul(interval)?;
+        match self.frequency {
+            Frequency::Once | Frequency::Daily => start.checked_add_days(Days::new(periods.into())),
+            Frequency::Weekly => start
+                .checked_sub_days(Days::new(start.weekday().num_days_from_monday().into()))?
+                .checked_add_days(Days::new(u64::from(periods) * 7)),
+            Frequency::Monthly => start.with_day(1)?.checked_add_months(Months::new(periods)),
+            Frequency::Yearly => start
+                .with_day(1)?
+                .with_month(1)?
+                .checked_add_months(Months::new(periods.checked_mul(12)?)),
+        }
+    }
+
+    /// Get the number of periods that can be skipped without missing any occurrences that happen
+    /// on or after `time`
+    fn periods_before(&self, start: NaiveDate, time: NaiveDate) -> u32 {
+        let units = match self.frequency {
+            Frequency::Once => 0,
+            Frequency::Daily => (time - start).num_days(),
+            Frequency::Weekly => (time - start).num_weeks(),
+            Frequency::Monthly => {
+                i64::from(time.year() - start.year()) * 12 + i64::from(time.month())
+                    - i64::from(start.month())
+            }
+            Frequency::Yearly => i64::from(time.year() - start.year()),
+        };
+        // Leave one period out to account for the periods not being aligned with `start`
+        (units / i64::from(self.interval.max(1)) - 1).clamp(0, u32::MAX.into()) as u32
+    }
+
+    /// Get the candidate start times within the period that begins on `period_start`, in
+    /// chronological order. Some of them may happen before `start`
+    fn candidates_in_period(
+        &self,
+        start: NaiveDateTime,
+        period_start: NaiveDate,
+    ) -> Vec<NaiveDateTime> {
+        // Find the days within the period that the event happens on
+        let days: Vec<NaiveDate> = match self.frequency {
+            Frequency::Once => vec![start.date()],
+            Frequency::Daily => vec![period_start],
+            Frequency::Weekly if self.by_day.is_empty() => period_start
+                .checked_add_days(Days::new(start.weekday().num_days_from_monday().into()))
+                .into_iter()
+                .collect(),
+            Frequency::Weekly => period_start.iter_days().take(7).collect(),
+            Frequency::Monthly if self.by_day.is_empty() => {
+                // Months without this day are skipped
+                period_start.with_day(start.day()).into_iter().collect()
+            }
+            Frequency::Monthly => period_start
+                .iter_days()
+                .take_while(|day| day.month() == period_start.month())
+                .collect(),
+            Frequency::Yearly => period_start
+                .with_month(start.month())
+                .and_then(|day| day.with_day(start.day()))
+                .into_iter()
+                .collect(),
+        };
+
+        days.into_iter()
+            // Only keep the days of the week that were chosen
+            .filter(|day| self.by_day.is_empty() || self.by_day.contains(&day.weekday().into()))
+            .map(|day| day.and_time(start.time()))
+            .collect()
+    }
+}
+
+/// Get the start times of all occurrences of an event that overlap with the time window
+/// `from` (inclusive) to `to` (exclusive), both of which are UNIX timestamps in seconds.
+/// The event repeats in the wall-clock time of its time zone, so it stays at the same local time
+/// when the clocks change
+pub fn occurrence_start_times(event: &Event, from: i64, to: i64) -> Vec<i64> {
+    let rule = event.recurrence_rule();
+    let time_zone = event.time_zone();
+    let start_time = event.start_time;
+    let duration = event.duration;
+    let until = rule.until;
+    let mut remaining_count = rule.count;
+
+    let (Some(start), Some(window_start), Some(window_end)) = (
+        to_local_time(start_time, time_zone),
+        to_local_time(from.saturating_sub(duration), time_zone),
+        to_local_time(to, time_zone),
+    ) else {
+        return vec![];
+    };
+
+    // The occurrences need to be counted from the beginning if their number is limited
+    let first_period = if rule.count.is_none() {
+        rule.periods_before(start.date(), window_start.date())
+    } else {
+        0
+    };
+
+    let mut occurrences = vec![];
+    for period in first_period.. {
+        let Some(period_start) = rule.period_start(start.date(), period) else {
+            break;
+        };
+        // Stop once the periods begin after the window
+        if period_start > window_end.date() {
+            break;
+        }
+
+        for time in rule.candidates_in_period(start, period_start) {
+            let Some(time) = to_timestamp(time, time_zone) else {
+                return occurrences;
+            };
+            // The event does not happen before it has begun
+            if time < start_time {
+                continue;
+            }
+            // Stop once the event no longer repeats
+            if matches!(until, Some(until) if time > until) || remaining_count == Some(0) {
+                return occurrences;
+            }
+            remaining_count = remaining_count.map(|count| count - 1);
+            if time >= to {
+                return occurrences;
+            }
+
+            // Only keep the occurrences that end after the window starts
+            if time.saturating_add(duration) > from {
+                occurrences.push(time);
+            }
+        }
+
+        // An event that happens once only has one period
+        if rule.frequency == Frequency::Once {
+            break;
+        }
+    }
+
+    occurrences
+}
+
+/// Check whether the recurrence rule of the event schedules an occurrence at `time`
+pub fn is_occurrence(event: &Event, time: i64) -> bool {
+    occurrence_start_times(event, time, time.saturating_add(1)).contains(&time)
+}
+
+/// Expand the events into their concrete occurrences within the time window `from` (inclusive)
+/// to `to` (exclusive), both of which are UNIX timestamps in seconds.
+/// The `exceptions` to the events are applied, so cancelled occurrences are left out and moved
+/// occurrences are shown at their new times.
+/// The occurrences are sorted by their start time
+pub fn expand_events(
+    events: &[Event],
+    exceptions: &[EventException],
+    from: i64,
+    to: i64,
+) -> Vec<EventOccurrence> {
+    let mut occurrences = vec![];
+
+    for event in events {
+        let event_exceptions: Vec<_> = exceptions
+            .iter()
+            .filter(|exception| exception.event_id == event.id)
+            .collect();
+
+        // Add the occurrences that happen as scheduled
+        for start_time in occurrence_start_times(event, from, to) {
+            let has_exception = event_exceptions
+                .iter()
+                .any(|exception| exception.original_start_time == start_time);
+            if !has_exception {
+                occurrences.push(EventOccurrence::new(
+                    event,
+                    start_time,
+                    start_time,
+                    event.duration,
+                ));
+            }
+        }
+
+        // Add the occurrences that were changed and now happen within the window
+        for exception in event_exceptions {
+            let Some((start_time, duration)) = exception.rescheduled_time(event) else {
+                continue;
+            };
+            let overlaps = start_time < to && start_time.saturating_add(duration) > from;
+            // Ignore the exceptions that do not refer to an actual occurrence
+            if overlaps && is_occurrence(event, exception.original_start_time) {
+                occurrences.push(EventOccurrence::new(
+                    event,
+                    exception.original_start_time,
+                    start_time,
+                    duration,
+                ));
+            }
+        }
+    }
+
+    occurrences.sort_by_key(|occurrence| occurrence.start_time);
+
+    occurrences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a wall-clock time such as `1997-09-02 09:00`
+    fn local(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    /// Get the UNIX timestamp of a wall-clock time in a time zone
+    fn timestamp(time: &str, time_zone: Tz) -> i64 {
+        to_timestamp(local(time), time_zone).unwrap()
+    }
+
+    fn rule(frequency: Frequency) -> RecurrenceRule {
+        RecurrenceRule {
+            frequency,
+            ..RecurrenceRule::once()
+        }
+    }
+
+    fn event(start: &str, time_zone: Tz, rule: RecurrenceRule) -> Event {
+        Event {
+            id: 1,
+            title: String::from("Event"),
+            visibility: VisibilityType::Public,
+            start_time: timestamp(start, time_zone),
+            duration: 60 * 60,
+            group_id: 1,
+            recurrence_frequency: rule.frequency,
+            recurrence_interval: rule.interval,
+            recurrence_by_day: rule.by_day,
+            recurrence_until: rule.until,
+            recurrence_count: rule.count,
+            time_zone: time_zone.name().to_string(),
+        }
+    }
+
+    /// Get the wall-clock times of the occurrences of an event between two wall-clock times
+    fn occurrences(event: &Event, from: &str, to: &str) -> Vec<String> {
+        let time_zone = event.time_zone();
+        occurrence_start_times(event, timestamp(from, time_zone), timestamp(to, time_zone))
+            .into_iter()
+            .map(|time| {
+                to_local_time(time, time_zone)
+                    .unwrap()
+                    .format("%Y-%m-%d %H:%M")
+                    .to_string()
+            })
+            .collect()
+    }
+
+    /// Format the dates of `days` in a month at 09:00, e.g. for the examples of RFC 5545
+    fn days_at_nine(year_month: &str, days: &[u32]) -> Vec<String> {
+        days.iter()
+            .map(|day| format!("{}-{:02} 09:00", year_month, day))
+            .collect()
+    }
+
+    const NEW_YORK: Tz = Tz::America__New_York;
+
+    // The examples of RFC 5545 section 3.8.5.3 start at 1997-09-02 09:00 in New York
+
+    #[test]
+    fn daily_for_10_occurrences() {
+        let event = event(
+            "1997-09-02 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                count: Some(10),
+                ..rule(Frequency::Daily)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00"),
+            days_at_nine("1997-09", &[2, 3, 4, 5, 6, 7, 8, 9, 10, 11])
+        );
+    }
+
+    #[test]
+    fn daily_until_stays_at_the_same_local_time_over_daylight_saving_changes() {
+        let event = event(
+            "1997-09-02 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                until: Some(timestamp("1997-12-24 00:00", Tz::UTC)),
+                ..rule(Frequency::Daily)
+            },
+        );
+
+        let times = occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00");
+        // 1997-09-02 to 1997-12-23
+        assert_eq!(times.len(), 113);
+        assert_eq!(times.first().unwrap(), "1997-09-02 09:00");
+        assert_eq!(times.last().unwrap(), "1997-12-23 09:00");
+        // The clocks went back on 1997-10-26
+        assert!(times.iter().all(|time| time.ends_with("09:00")));
+    }
+
+    #[test]
+    fn every_10_days_for_5_occurrences() {
+        let event = event(
+            "1997-09-02 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                interval: 10,
+                count: Some(5),
+                ..rule(Frequency::Daily)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00"),
+            [
+                days_at_nine("1997-09", &[2, 12, 22]),
+                days_at_nine("1997-10", &[2, 12]),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn weekly_for_10_occurrences() {
+        let event = event(
+            "1997-09-02 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                count: Some(10),
+                ..rule(Frequency::Weekly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00"),
+            [
+                days_at_nine("1997-09", &[2, 9, 16, 23, 30]),
+                days_at_nine("1997-10", &[7, 14, 21, 28]),
+                days_at_nine("1997-11", &[4]),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn weekly_on_tuesday_and_thursday_for_5_weeks() {
+        let event = event(
+            "1997-09-02 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                by_day: vec![Weekday::Tuesday, Weekday::Thursday],
+                until: Some(timestamp("1997-10-07 00:00", Tz::UTC)),
+                ..rule(Frequency::Weekly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00"),
+            [
+                days_at_nine("1997-09", &[2, 4, 9, 11, 16, 18, 23, 25, 30]),
+                days_at_nine("1997-10", &[2]),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn every_other_week_on_monday_wednesday_and_friday() {
+        let event = event(
+            "1997-09-01 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                interval: 2,
+                by_day: vec![Weekday::Monday, Weekday::Wednesday, Weekday::Friday],
+                until: Some(timestamp("1997-12-24 00:00", Tz::UTC)),
+                ..rule(Frequency::Weekly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00"),
+            [
+                days_at_nine("1997-09", &[1, 3, 5, 15, 17, 19, 29]),
+                days_at_nine("1997-10", &[1, 3, 13, 15, 17, 27, 29, 31]),
+                days_at_nine("1997-11", &[10, 12, 14, 24, 26, 28]),
+                days_at_nine("1997-12", &[8, 10, 12, 22]),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn every_other_week_on_tuesday_and_thursday_for_8_occurrences() {
+        let event = event(
+            "1997-09-02 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                interval: 2,
+                by_day: vec![Weekday::Tuesday, Weekday::Thursday],
+                count: Some(8),
+                ..rule(Frequency::Weekly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00"),
+            [
+                days_at_nine("1997-09", &[2, 4, 16, 18, 30]),
+                days_at_nine("1997-10", &[2, 14, 16]),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn monthly_on_every_tuesday() {
+        let event = event(
+            "1997-09-02 09:00",
+            NEW_YORK,
+            RecurrenceRule {
+                by_day: vec![Weekday::Tuesday],
+                count: Some(7),
+                ..rule(Frequency::Monthly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "1997-01-01 00:00", "1999-01-01 00:00"),
+            [
+                days_at_nine("1997-09", &[2, 9, 16, 23, 30]),
+                days_at_nine("1997-10", &[7, 14]),
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn monthly_on_the_31st_skips_the_shorter_months() {
+        let event = event(
+            "2023-01-31 09:00",
+            Tz::UTC,
+            RecurrenceRule {
+                count: Some(4),
+                ..rule(Frequency::Monthly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "2023-01-01 00:00", "2024-01-01 00:00"),
+            [
+                "2023-01-31 09:00",
+                "2023-03-31 09:00",
+                "2023-05-31 09:00",
+                "2023-07-31 09:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn yearly_on_29_february_only_happens_in_leap_years() {
+        let event = event(
+            "2024-02-29 09:00",
+            Tz::UTC,
+            RecurrenceRule {
+                count: Some(3),
+                ..rule(Frequency::Yearly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "2024-01-01 00:00", "2040-01-01 00:00"),
+            ["2024-02-29 09:00", "2028-02-29 09:00", "2032-02-29 09:00"]
+        );
+    }
+
+    #[test]
+    fn times_skipped_by_daylight_saving_are_moved_forward() {
+        // The clocks went from 02:00 to 03:00 on 2023-03-12
+        let event = event("2023-03-11 02:30", NEW_YORK, rule(Frequency::Daily));
+
+        assert_eq!(
+            occurrences(&event, "2023-03-11 00:00", "2023-03-14 00:00"),
+            ["2023-03-11 02:30", "2023-03-12 03:30", "2023-03-13 02:30"]
+        );
+    }
+
+    #[test]
+    fn times_repeated_by_daylight_saving_use_the_first_one() {
+        // The clocks went from 02:00 back to 01:00 on 2023-11-05
+        let time = timestamp("2023-11-05 01:30", NEW_YORK);
+        assert_eq!(time, timestamp("2023-11-05 05:30", Tz::UTC));
+    }
+
+    #[test]
+    fn skips_the_periods_before_the_window() {
+        let event = event("2000-01-03 09:00", Tz::UTC, rule(Frequency::Weekly));
+
+        assert_eq!(
+            occurrences(&event, "2023-01-01 00:00", "2023-01-20 00:00"),
+            ["2023-01-02 09:00", "2023-01-09 09:00", "2023-01-16 09:00"]
+        );
+    }
+
+    #[test]
+    fn counts_the_occurrences_before_the_window() {
+        let event = event(
+            "2023-01-02 09:00",
+            Tz::UTC,
+            RecurrenceRule {
+                count: Some(3),
+                ..rule(Frequency::Weekly)
+            },
+        );
+
+        assert_eq!(
+            occurrences(&event, "2023-01-10 00:00", "2023-03-01 00:00"),
+            ["2023-01-16 09:00"]
+        );
+    }
+
+    #[test]
+    fn includes_the_occurrences_that_started_before_the_window() {
+        let event = event("2023-01-02 09:00", Tz::UTC, rule(Frequency::Daily));
+
+        assert_eq!(
+            occurrences(&event, "2023-01-03 09:30", "2023-01-04 09:00"),
+            ["2023-01-03 09:00"]
+        );
+    }
+
+    #[test]
+    fn checks_whether_a_time_is_an_occurrence() {
+        let event = event("2023-01-02 09:00", Tz::UTC, rule(Frequency::Weekly));
+
+        assert!(is_occurrence(
+            &event,
+            timestamp("2023-01-09 09:00", Tz::UTC)
+        ));
+        assert!(!is_occurrence(
+            &event,
+            timestamp("2023-01-10 09:00", Tz::UTC)
+        ));
+        assert!(!is_occurrence(
+            &event,
+            timestamp("2022-12-26 09:00", Tz::UTC)
+        ));
+    }
+
+    #[test]
+    fn applies_the_exceptions() {
+        let event = event("2023-01-02 09:00", Tz::UTC, rule(Frequency::Daily));
+        let exception = |original: &str, is_cancelled, new_start: Option<&str>| EventException {
+            id: 1,
+            event_id: event.id,
+            original_start_time: timestamp(original, Tz::UTC),
+            is_cancelled,
+            new_start_time: new_start.map(|time| timestamp(time, Tz::UTC)),
+            new_duration: None,
+        };
+        let exceptions = [
+            exception("2023-01-03 09:00", true, None),
+            exception("2023-01-04 09:00", false, Some("2023-01-05 18:00")),
+            // Not an occurrence, so it is ignored
+            exception("2023-01-05 10:00", false, Some("2023-01-05 12:00")),
+        ];
+
+        let times: Vec<_> = expand_events(
+            &[event],
+            &exceptions,
+            timestamp("2023-01-02 00:00", Tz::UTC),
+            timestamp("2023-01-06 00:00", Tz::UTC),
+        )
+        .into_iter()
+        .map(|occurrence| occurrence.start_time)
+        .collect();
+
+        assert_eq!(
+            times,
+            [
+                timestamp("2023-01-02 09:00", Tz::UTC),
+                timestamp("2023-01-05 09:00", Tz::UTC),
+                timestamp("2023-01-05 18:00", Tz::UTC),
+            ]
+        );
+    }
+
+    #[test]
+    fn validates_the_rules() {
+        let start_time = timestamp("2023-01-02 09:00", Tz::UTC);
+        let check = |rule: RecurrenceRule| rule.validate(start_time, Tz::UTC);
+
+        assert!(check(RecurrenceRule::once()).is_ok());
+        assert!(check(RecurrenceRule {
+            count: Some(2),
+            ..RecurrenceRule::once()
+        })
+        .is_err());
+        assert!(check(RecurrenceRule {
+            interval: 0,
+            ..rule(Frequency::Daily)
+        })
+        .is_err());
+        assert!(check(RecurrenceRule {
+            count: Some(2),
+            until: Some(start_time),
+            ..rule(Frequency::Daily)
+        })
+        .is_err());
+        assert!(check(RecurrenceRule {
+            until: Some(start_time - 1),
+            ..rule(Frequency::Daily)
+        })
+        .is_err());
+        // 2023-01-02 was a Monday
+        assert!(check(RecurrenceRule {
+            by_day: vec![Weekday::Tuesday],
+            ..rule(Frequency::Weekly)
+        })
+        .is_err());
+        assert!(check(RecurrenceRule {
+            by_day: vec![Weekday::Monday],
+            ..rule(Frequency::Yearly)
+        })
+        .is_err());
+    }
+}
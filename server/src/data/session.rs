@@ -1,49 +1,285 @@
-use super::users::User;
-use actix_session::{Session, SessionInsertError};
-
-macro_rules! use_session {
-    ($session: expr, $user_var: ident) => {
-        use $crate::data::session::get_session;
-        use $crate::endpoints::EndpointError;
-        let $user_var = get_session($session);
-        if $user_var.is_none() {
-            return Result::Err(EndpointError::BadClientData(
-                "Could not authenticate you. Please go to the login page.",
-            ));
+use super::{
+    api_tokens::{ApiToken, TokenScope},
+    models::{random_token, UnsavedModel},
+    users::User,
+};
+use crate::{
+    csrf::{get_csrf_token, set_csrf_token},
+    endpoints::EndpointError,
+    schema::{sessions, users},
+    ServerState,
+};
+use actix_session::{Session, SessionExt, SessionInsertError};
+use actix_web::{dev::Payload, http::header, web, FromRequest, HttpMessage, HttpRequest};
+use chrono::Utc;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{future::Future, pin::Pin};
+
+/// The key the session token is stored under in the session cookie
+const TOKEN_KEY: &str = "token";
+/// The key a login that is waiting for a two-factor code is stored under in the session cookie
+const PENDING_TWO_FACTOR_KEY: &str = "pending_two_factor";
+
+/// A user who has entered the right password, but still has to enter a two-factor code.
+/// It is only kept in the session cookie, which is encrypted so that it can not be forged
+#[derive(Serialize, Deserialize)]
+struct PendingTwoFactorLogin {
+    user_id: i32,
+    /// The UNIX timestamp, in seconds, after which the password has to be entered again
+    expires_at: i64,
+}
+
+/// The user that is logged in with the session of a request, or whose personal API token was sent
+/// in the `Authorization: Bearer` header.
+/// Handlers that take it as an argument reject the requests that are not logged in
+pub struct AuthenticatedUser(pub User);
+
+impl FromRequest for AuthenticatedUser {
+    type Error = EndpointError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+
+        Box::pin(async move {
+            // Reuse the user if it has already been loaded for this request
+            if let Some(user) = req.extensions().get::<User>() {
+                return Ok(AuthenticatedUser(user.clone()));
+            }
+
+            let server_state = req
+                .app_data::<web::Data<ServerState>>()
+                .ok_or(EndpointError::InternalError)?;
+            // The user is loaded from the database, so a deleted user, an ended session or a
+            // revoked token is rejected
+            let user = match bearer_token(&req) {
+                Some(token) => get_api_token_user(&req, token, server_state).await?,
+                None => get_session(&req.get_session(), server_state)
+                    .await?
+                    .ok_or(EndpointError::NotLoggedIn)?,
+            };
+
+            req.extensions_mut().insert(user.clone());
+            Ok(AuthenticatedUser(user))
+        })
+    }
+}
+
+/// Get the personal API token in the `Authorization` header, if there is one
+pub fn bearer_token(req: &HttpRequest) -> Option<String> {
+    let header = req.headers().get(header::AUTHORIZATION)?.to_str().ok()?;
+    header
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_string())
+}
+
+/// Get the user that a personal API token belongs to, if the token has the scope that the
+/// endpoint of the request needs
+async fn get_api_token_user(
+    req: &HttpRequest,
+    token: String,
+    server_state: &ServerState,
+) -> Result<User, EndpointError> {
+    let required_scope = req
+        .match_pattern()
+        .and_then(|route| TokenScope::required_for(&route))
+        .ok_or(EndpointError::NotAllowed(
+            "API tokens can not be used for this endpoint.",
+        ))?;
+
+    // Query the database on the thread pool for blocking operations
+    let (api_token, user) = server_state
+        .with_connection(move |connection| {
+            ApiToken::get_with_user_by_token(connection, &token).map_err(|err| {
+                // Log the error
+                log::error!("session.get_api_token_user: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?
+        .ok_or(EndpointError::NotLoggedIn)?;
+
+    if !api_token.scopes.contains(&required_scope) {
+        return Err(EndpointError::NotAllowed(
+            "This API token does not have the scope this endpoint needs.",
+        ));
+    }
+
+    Ok(user)
+}
+
+/// A struct that represents a session a user is logged in with, e.g. on one of their devices
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(User, foreign_key = user_id))]
+#[diesel(table_name = sessions)]
+pub struct UserSession {
+    pub id: i32,
+    pub user_id: i32,
+    /// The secret that is stored in the session cookie
+    pub token: String,
+    pub created_at: i64,
+    /// When the session ends, even if it is still used
+    pub expires_at: i64,
+}
+
+/// A session that has not been saved to the database yet
+#[derive(Insertable)]
+#[diesel(table_name = sessions)]
+pub struct UnsavedUserSession {
+    pub user_id: i32,
+    pub token: String,
+    pub created_at: i64,
+    pub expires_at: i64,
+}
+
+impl UnsavedUserSession {
+    /// Create a session for the user with a new random token that lasts for `lifetime` seconds
+    pub fn new(user: &User, lifetime: i64) -> Self {
+        let now = Utc::now().timestamp();
+
+        UnsavedUserSession {
+            user_id: user.id,
+            token: random_token(),
+            created_at: now,
+            expires_at: now.saturating_add(lifetime),
         }
+    }
+}
 
-        // Expose the user
-        // Safety: we know it is not None
-        let $user_var = $user_var.unwrap();
-    };
+impl UnsavedModel<UserSession> for UnsavedUserSession {
+    fn save(self, connection: &mut PgConnection) -> QueryResult<UserSession> {
+        diesel::insert_into(sessions::dsl::sessions)
+            .values(self)
+            .get_result(connection)
+    }
+}
+
+impl UserSession {
+    /// Find the user that the session with this token belongs to, unless the session has expired
+    pub fn get_user_by_token(
+        connection: &mut PgConnection,
+        token: &str,
+    ) -> QueryResult<Option<User>> {
+        users::table
+            .inner_join(sessions::table)
+            .filter(sessions::token.eq(token))
+            .filter(sessions::expires_at.gt(Utc::now().timestamp()))
+            .select(users::all_columns)
+            .first(connection)
+            .optional()
+    }
+
+    /// End the session with this token
+    pub fn delete_by_token(connection: &mut PgConnection, token: &str) -> QueryResult<usize> {
+        diesel::delete(sessions::table.filter(sessions::token.eq(token))).execute(connection)
+    }
+
+    /// End all the sessions of the user, so that they are logged out on all their devices
+    pub fn delete_all_for_user(connection: &mut PgConnection, user_id: i32) -> QueryResult<usize> {
+        diesel::delete(sessions::table.filter(sessions::user_id.eq(user_id))).execute(connection)
+    }
+
+    /// End all the sessions of the user except the one with `current_token`, e.g. after the
+    /// password is changed on the current device
+    pub fn delete_others_for_user(
+        connection: &mut PgConnection,
+        user_id: i32,
+        current_token: &str,
+    ) -> QueryResult<usize> {
+        diesel::delete(
+            sessions::table
+                .filter(sessions::user_id.eq(user_id))
+                .filter(sessions::token.ne(current_token)),
+        )
+        .execute(connection)
+    }
+
+    /// Remove the sessions that have expired, as they can not be used any more
+    pub fn delete_expired(connection: &mut PgConnection) -> QueryResult<usize> {
+        diesel::delete(sessions::table.filter(sessions::expires_at.le(Utc::now().timestamp())))
+            .execute(connection)
+    }
+}
+
+/// Get the token of the session in the cookie, if there is one
+pub fn get_session_token(session: &Session) -> Option<String> {
+    session.get(TOKEN_KEY).ok().flatten()
 }
 
-// Export the macro
-pub(crate) use use_session;
-
-// Tries to get a user from a session
-pub fn get_session(session: Session) -> Option<User> {
-    if let (Ok(Some(id)), Ok(Some(username)), Ok(Some(password_hash))) = (
-        session.get("id"),
-        session.get("username"),
-        session.get("password_hash"),
-    ) {
-        Some(User {
-            id,
-            username,
-            password_hash,
+/// Tries to get the user that is logged in with a session.
+/// The session is checked against the database, so that it stops working as soon as it is ended
+pub async fn get_session(
+    session: &Session,
+    server_state: &ServerState,
+) -> Result<Option<User>, EndpointError> {
+    let token = match get_session_token(session) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    // Query the database on the thread pool for blocking operations
+    let user = server_state
+        .with_connection(move |connection| {
+            UserSession::get_user_by_token(connection, &token).map_err(|err| {
+                // Log the error
+                log::error!("session.get_session: {}", err);
+                EndpointError::InternalError
+            })
         })
-    } else {
-        None
+        .await?;
+
+    // Forget a session that has ended
+    if user.is_none() {
+        session.purge();
     }
+
+    Ok(user)
+}
+
+/// Remembers a session in the cookie. Only the token is stored there
+/// Returns an Error if it can not serialise the token as JSON
+pub fn set_session(
+    session: &Session,
+    user_session: &UserSession,
+) -> Result<(), SessionInsertError> {
+    // Start a new cookie, so that a cookie from before logging in can not be reused
+    session.renew();
+    session.clear();
+    session.insert(TOKEN_KEY, &user_session.token)
 }
 
-/// Remembers the user for a session
-/// Returns an Error if it can not serialise the user fields as JSON
-pub fn set_session(session: Session, user: &User) -> Result<(), SessionInsertError> {
-    session.insert("id", user.id)?;
-    session.insert("username", &user.username)?;
-    session.insert("password_hash", &user.password_hash)?;
+/// Remembers in the cookie that the user has entered the right password, so that they can log in
+/// with a two-factor code within `timeout` seconds
+pub fn set_pending_two_factor(
+    session: &Session,
+    user: &User,
+    timeout: i64,
+) -> Result<(), SessionInsertError> {
+    // The login page still has to send the code, so it needs the same CSRF token
+    let csrf_token = get_csrf_token(session);
+
+    // Start a new cookie, so that the user is no longer logged in with a previous session
+    session.renew();
+    session.clear();
+    if let Some(csrf_token) = csrf_token {
+        set_csrf_token(session, &csrf_token)?;
+    }
+    session.insert(
+        PENDING_TWO_FACTOR_KEY,
+        PendingTwoFactorLogin {
+            user_id: user.id,
+            expires_at: Utc::now().timestamp().saturating_add(timeout),
+        },
+    )
+}
 
-    Ok(())
+/// Get the ID of the user who still has to enter a two-factor code, unless it took them too long
+pub fn get_pending_two_factor(session: &Session) -> Option<i32> {
+    session
+        .get::<PendingTwoFactorLogin>(PENDING_TWO_FACTOR_KEY)
+        .ok()
+        .flatten()
+        .filter(|pending| pending.expires_at > Utc::now().timestamp())
+        .map(|pending| pending.user_id)
 }
@@ -0,0 +1,450 @@
+use chrono::Utc;
+use diesel::{pg::upsert::excluded, prelude::*};
+use openssl::{error::ErrorStack, hash::MessageDigest, memcmp, pkey::PKey, sign::Signer};
+use rand_core::{OsRng, RngCore};
+
+use crate::{
+    schema::{recovery_codes, two_factor_secrets},
+    settings::{
+        RECOVERY_CODE_COUNT, TWO_FACTOR_ALLOWED_DRIFT, TWO_FACTOR_ISSUER, TWO_FACTOR_PERIOD,
+    },
+};
+
+use super::{
+    models::UnsavedModel,
+    users::{verify_hash, User},
+};
+
+/// The number of random bytes in a secret, as recommended by RFC 4226
+const SECRET_BYTES: usize = 20;
+/// The number of digits in a two-factor code
+const CODE_DIGITS: usize = 6;
+/// The characters that base32 encodes 5 bits each with, from RFC 4648
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+/// The characters recovery codes are made of. Ones that are easy to confuse, like 0 and o, are left
+/// out
+const RECOVERY_CODE_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+/// The number of characters in a recovery code, not counting the dash in the middle
+const RECOVERY_CODE_LENGTH: usize = 10;
+
+/// A struct that represents the secret a user's authenticator app generates codes with
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(User, foreign_key = user_id))]
+#[diesel(table_name = two_factor_secrets)]
+pub struct TwoFactorSecret {
+    pub id: i32,
+    pub user_id: i32,
+    pub secret: Vec<u8>,
+    /// Whether the user has confirmed that their app works, after which a code is needed to log in
+    pub is_enabled: bool,
+    /// The time step of the last code that was accepted, so that a code can not be used twice
+    pub last_used_step: Option<i64>,
+}
+
+/// A two-factor secret that has not been saved to the database yet
+#[derive(Insertable)]
+#[diesel(table_name = two_factor_secrets)]
+pub struct UnsavedTwoFactorSecret {
+    pub user_id: i32,
+    pub secret: Vec<u8>,
+    pub is_enabled: bool,
+}
+
+/// A struct that represents a recovery code, which can be used once instead of a two-factor code,
+/// e.g. when the phone with the authenticator app is lost
+#[derive(Identifiable, Queryable, Associations, Debug)]
+#[diesel(belongs_to(User, foreign_key = user_id))]
+#[diesel(table_name = recovery_codes)]
+pub struct RecoveryCode {
+    pub id: i32,
+    pub user_id: i32,
+    /// The code is hashed like a password, as it can be used to log in
+    pub code_hash: String,
+}
+
+/// A recovery code that has not been saved to the database yet
+#[derive(Insertable)]
+#[diesel(table_name = recovery_codes)]
+pub struct UnsavedRecoveryCode {
+    pub user_id: i32,
+    pub code_hash: String,
+}
+
+impl UnsavedTwoFactorSecret {
+    /// Create a secret for the user that is not enabled until they confirm a code
+    pub fn new(user: &User) -> Self {
+        let mut secret = vec![0u8; SECRET_BYTES];
+        OsRng.fill_bytes(&mut secret);
+
+        UnsavedTwoFactorSecret {
+            user_id: user.id,
+            secret,
+            is_enabled: false,
+        }
+    }
+}
+
+impl UnsavedModel<TwoFactorSecret> for UnsavedTwoFactorSecret {
+    /// Save the secret, replacing the one the user had, e.g. if they started setting up two-factor
+    /// authentication before but did not finish
+    fn save(self, connection: &mut PgConnection) -> QueryResult<TwoFactorSecret> {
+        use crate::schema::two_factor_secrets::dsl::*;
+
+        diesel::insert_into(two_factor_secrets)
+            .values(&self)
+            .on_conflict(user_id)
+            .do_update()
+            .set((
+                secret.eq(excluded(secret)),
+                is_enabled.eq(excluded(is_enabled)),
+                last_used_step.eq(None::<i64>),
+            ))
+            .get_result(connection)
+    }
+}
+
+impl UnsavedModel<RecoveryCode> for UnsavedRecoveryCode {
+    fn save(self, connection: &mut PgConnection) -> QueryResult<RecoveryCode> {
+        diesel::insert_into(recovery_codes::dsl::recovery_codes)
+            .values(self)
+            .get_result(connection)
+    }
+}
+
+impl TwoFactorSecret {
+    /// Get the secret of the user, if they have started setting up two-factor authentication
+    pub fn get_for_user(
+        connection: &mut PgConnection,
+        user_id: i32,
+    ) -> QueryResult<Option<TwoFactorSecret>> {
+        two_factor_secrets::table
+            .filter(two_factor_secrets::user_id.eq(user_id))
+            .first(connection)
+            .optional()
+    }
+
+    /// Check if the user has to enter a two-factor code to log in
+    pub fn is_enabled_for_user(connection: &mut PgConnection, user_id: i32) -> QueryResult<bool> {
+        let secret = TwoFactorSecret::get_for_user(connection, user_id)?;
+        Ok(matches!(secret, Some(secret) if secret.is_enabled))
+    }
+
+    /// Get the URI that authenticator apps are set up with, usually by scanning it as a QR code.
+    /// The format is described at https://github.com/google/google-authenticator/wiki/Key-Uri-Format
+    pub fn otpauth_uri(&self, username: &str) -> String {
+        let issuer = percent_encode(TWO_FACTOR_ISSUER);
+        format!(
+            "otpauth://totp/{issuer}:{}?secret={}&issuer={issuer}&algorithm=SHA1&digits={CODE_DIGITS}&period={TWO_FACTOR_PERIOD}",
+            percent_encode(username),
+            self.secret_base32(),
+        )
+    }
+
+    /// Get the secret in the form that can be typed into authenticator apps
+    pub fn secret_base32(&self) -> String {
+        base32_encode(&self.secret)
+    }
+
+    /// Check a code from the authenticator app of the user. An accepted code can not be used again
+    pub fn check_code(&self, connection: &mut PgConnection, code: &str) -> QueryResult<bool> {
+        use crate::schema::two_factor_secrets::dsl::*;
+
+        let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+        if code.len() != CODE_DIGITS || !code.chars().all(|c| c.is_ascii_digit()) {
+            return Ok(false);
+        }
+
+        let current_step = Utc::now().timestamp() / TWO_FACTOR_PERIOD;
+        let matching_step = (current_step - TWO_FACTOR_ALLOWED_DRIFT
+            ..=current_step + TWO_FACTOR_ALLOWED_DRIFT)
+            // None is less than any step, so every step is allowed if no code has been used yet
+            .filter(|step| Some(*step) > self.last_used_step)
+            .find(|step| {
+                let expected = totp_code(&self.secret, *step)
+                    .expect("Could not calculate the two-factor code");
+                memcmp::eq(expected.as_bytes(), code.as_bytes())
+            });
+
+        let step = match matching_step {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+
+        // Only accept the code if no other request has used a code from this or a later step
+        // at the same time
+        let updated = diesel::update(
+            two_factor_secrets
+                .find(self.id)
+                .filter(last_used_step.is_null().or(last_used_step.lt(step))),
+        )
+        .set(last_used_step.eq(step))
+        .execute(connection)?;
+
+        Ok(updated == 1)
+    }
+
+    /// Require a code to log in from now on, giving the user recovery codes with these hashes.
+    /// Any recovery codes they had before stop working
+    pub fn enable(
+        &self,
+        connection: &mut PgConnection,
+        recovery_code_hashes: Vec<String>,
+    ) -> QueryResult<()> {
+        connection.transaction(|connection| {
+            diesel::update(self)
+                .set(two_factor_secrets::is_enabled.eq(true))
+                .execute(connection)?;
+
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(self.user_id)))
+                .execute(connection)?;
+            for code_hash in recovery_code_hashes {
+                UnsavedRecoveryCode {
+                    user_id: self.user_id,
+                    code_hash,
+                }
+                .save(connection)?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Turn off two-factor authentication for the user, deleting their secret and recovery codes
+    pub fn delete_for_user(connection: &mut PgConnection, user_id: i32) -> QueryResult<()> {
+        connection.transaction(|connection| {
+            diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(user_id)))
+                .execute(connection)?;
+            diesel::delete(
+                two_factor_secrets::table.filter(two_factor_secrets::user_id.eq(user_id)),
+            )
+            .execute(connection)?;
+
+            Ok(())
+        })
+    }
+}
+
+impl RecoveryCode {
+    /// Generate new random recovery codes, formatted like abcde-fghjk so that they are easy to
+    /// copy down
+    pub fn generate() -> Vec<String> {
+        (0..RECOVERY_CODE_COUNT)
+            .map(|_| {
+                let characters: String = (0..RECOVERY_CODE_LENGTH)
+                    .map(|_| {
+                        let index = OsRng.next_u32() as usize % RECOVERY_CODE_ALPHABET.len();
+                        RECOVERY_CODE_ALPHABET[index] as char
+                    })
+                    .collect();
+                let (first, second) = characters.split_at(RECOVERY_CODE_LENGTH / 2);
+                format!("{first}-{second}")
+            })
+            .collect()
+    }
+
+    /// Remove the formatting from a recovery code the user entered, so that it can be hashed or
+    /// checked against a hash
+    pub fn normalise(code: &str) -> String {
+        code.chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect()
+    }
+
+    /// Use up one of the recovery codes of the user, returning whether `code` was one of them
+    pub fn use_code(connection: &mut PgConnection, user_id: i32, code: &str) -> QueryResult<bool> {
+        let code = RecoveryCode::normalise(code);
+        if code.len() != RECOVERY_CODE_LENGTH {
+            return Ok(false);
+        }
+
+        let codes: Vec<RecoveryCode> = recovery_codes::table
+            .filter(recovery_codes::user_id.eq(user_id))
+            .load(connection)?;
+
+        match codes
+            .into_iter()
+            .find(|recovery_code| verify_hash(&code, &recovery_code.code_hash))
+        {
+            // The code only counts if this request is the one that deleted it
+            Some(recovery_code) => Ok(diesel::delete(&recovery_code).execute(connection)? == 1),
+            None => Ok(false),
+        }
+    }
+
+    /// Count the recovery codes the user has not used yet
+    pub fn count_for_user(connection: &mut PgConnection, user_id: i32) -> QueryResult<i64> {
+        recovery_codes::table
+            .filter(recovery_codes::user_id.eq(user_id))
+            .count()
+            .get_result(connection)
+    }
+}
+
+/// Calculate the code for a time step with the TOTP algorithm from RFC 6238, which is HOTP from
+/// RFC 4226 with the time step as the counter
+fn totp_code(secret: &[u8], step: i64) -> Result<String, ErrorStack> {
+    let key = PKey::hmac(secret)?;
+    let mut signer = Signer::new(MessageDigest::sha1(), &key)?;
+    signer.update(&step.to_be_bytes())?;
+    let hmac = signer.sign_to_vec()?;
+
+    // Take 31 bits from the position given by the last 4 bits
+    let offset = (hmac[hmac.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hmac[offset] & 0x7f,
+        hmac[offset + 1],
+        hmac[offset + 2],
+        hmac[offset + 3],
+    ]);
+
+    Ok(format!(
+        "{:0width$}",
+        value % 10u32.pow(CODE_DIGITS as u32),
+        width = CODE_DIGITS
+    ))
+}
+
+/// Encode bytes as base32 without padding, which is how authenticator apps expect the secret
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+        // Only keep the bits that have not been encoded yet
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+
+    encoded
+}
+
+/// Encode the characters that are not allowed in a part of a URI
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The secret of the SHA-1 test vectors in appendix B of RFC 6238
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    /// Decode base32 without padding, the way authenticator apps read the secret
+    fn base32_decode(text: &str) -> Vec<u8> {
+        let mut bytes = vec![];
+        let mut buffer: u32 = 0;
+        let mut bits = 0;
+        for character in text.bytes() {
+            let value = BASE32_ALPHABET
+                .iter()
+                .position(|c| *c == character)
+                .unwrap();
+            buffer = (buffer << 5) | value as u32;
+            bits += 5;
+            if bits >= 8 {
+                bits -= 8;
+                bytes.push((buffer >> bits) as u8);
+                buffer &= (1 << bits) - 1;
+            }
+        }
+        // The bits left over only pad the last character
+        assert_eq!(buffer, 0);
+        bytes
+    }
+
+    #[test]
+    fn calculates_the_rfc_6238_test_vectors() {
+        // The RFC gives 8 digit codes, which end with the 6 digit ones
+        let vectors = [
+            (59, "94287082"),
+            (1111111109, "07081804"),
+            (1111111111, "14050471"),
+            (1234567890, "89005924"),
+            (2000000000, "69279037"),
+            (20000000000, "65353130"),
+        ];
+        for (time, code) in vectors {
+            assert_eq!(
+                totp_code(RFC_SECRET, time / TWO_FACTOR_PERIOD).unwrap(),
+                code[code.len() - CODE_DIGITS..],
+                "code at {time}"
+            );
+        }
+    }
+
+    #[test]
+    fn encodes_the_rfc_4648_test_vectors_without_padding() {
+        let vectors = [
+            ("", ""),
+            ("f", "MY"),
+            ("fo", "MZXQ"),
+            ("foo", "MZXW6"),
+            ("foob", "MZXW6YQ"),
+            ("fooba", "MZXW6YTB"),
+            ("foobar", "MZXW6YTBOI"),
+        ];
+        for (bytes, encoded) in vectors {
+            assert_eq!(base32_encode(bytes.as_bytes()), encoded);
+            assert_eq!(base32_decode(encoded), bytes.as_bytes());
+        }
+    }
+
+    #[test]
+    fn encodes_secrets_that_decode_to_the_same_bytes() {
+        assert_eq!(
+            base32_encode(RFC_SECRET),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+        let bytes: Vec<u8> = (0..=255).collect();
+        for length in 0..=SECRET_BYTES {
+            let bytes = &bytes[256 - length..];
+            assert_eq!(base32_decode(&base32_encode(bytes)), bytes);
+        }
+    }
+
+    #[test]
+    fn builds_otpauth_uris() {
+        let secret = TwoFactorSecret {
+            id: 1,
+            user_id: 1,
+            secret: RFC_SECRET.to_vec(),
+            is_enabled: false,
+            last_used_step: None,
+        };
+
+        assert_eq!(
+            secret.otpauth_uri("alice smith"),
+            format!(
+                "otpauth://totp/{TWO_FACTOR_ISSUER}:alice%20smith?secret=GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ\
+                &issuer={TWO_FACTOR_ISSUER}&algorithm=SHA1&digits=6&period=30"
+            )
+        );
+    }
+
+    #[test]
+    fn normalises_recovery_codes() {
+        for code in RecoveryCode::generate() {
+            assert_eq!(code.len(), RECOVERY_CODE_LENGTH + 1);
+            assert_eq!(RecoveryCode::normalise(&code).len(), RECOVERY_CODE_LENGTH);
+        }
+        assert_eq!(RecoveryCode::normalise(" ABCDE-fghjk\n"), "abcdefghjk");
+    }
+}
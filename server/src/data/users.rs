@@ -5,7 +5,9 @@ use rand_core::OsRng;
 
 use serde::Serialize;
 
-use crate::schema::users;
+use crate::schema::{
+    calendar_feeds, event_exceptions, events, friendships, groups, groups_participants, users,
+};
 
 use super::models::UnsavedModel;
 
@@ -25,7 +27,7 @@ pub struct UserPublic {
     pub id: i32,
 }
 
-#[derive(Identifiable, Queryable, Debug)]
+#[derive(Identifiable, Queryable, Clone, Debug)]
 /// A user struct that represents a user record in a database
 pub struct User {
     pub id: i32,
@@ -51,6 +53,17 @@ impl UnsavedUser {
         })
     }
 }
+
+/// Check that `text` is what was hashed into `hash` by `UnsavedUser::hash`
+pub fn verify_hash(text: &str, hash: &str) -> bool {
+    // Hash the text
+    let verifier = Argon2::default();
+    let hash = PasswordHash::new(hash).expect("Could not hash the password");
+
+    // Check the hash
+    verifier.verify_password(text.as_bytes(), &hash).is_ok()
+}
+
 impl UnsavedModel<User> for UnsavedUser {
     fn save(self, connection: &mut PgConnection) -> QueryResult<User> {
         diesel::insert_into(users::dsl::users)
@@ -81,24 +94,74 @@ impl User {
             .expect("Error loading users")
             .pop();
 
-        if let Some(user) = user {
-            // Hash the password
-            let password_verifier = Argon2::default();
-            let hash = PasswordHash::new(&user.password_hash).expect("Could not hash the password");
-
-            // Check the password hash
-            let is_password_correct = password_verifier
-                .verify_password(provided_password.as_bytes(), &hash)
-                .is_ok();
-
-            // Only return a user if the password is correct
-            if is_password_correct {
-                Some(user)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
+        // Only return a user if the password is correct
+        user.filter(|user| user.check_password(&provided_password))
+    }
+
+    /// Load the user with this ID from the database, if they still exist
+    pub fn fetch_by_id(connection: &mut PgConnection, user_id: i32) -> QueryResult<Option<User>> {
+        users::table.find(user_id).first(connection).optional()
+    }
+
+    /// Check that `provided_password` is the password of the user
+    pub fn check_password(&self, provided_password: &str) -> bool {
+        verify_hash(provided_password, &self.password_hash)
+    }
+
+    /// Replace the password hash of the user
+    pub fn set_password_hash(
+        &self,
+        connection: &mut PgConnection,
+        new_password_hash: &str,
+    ) -> QueryResult<User> {
+        diesel::update(users::table.find(self.id))
+            .set(users::password_hash.eq(new_password_hash))
+            .get_result(connection)
+    }
+
+    /// Delete the user together with everything that belongs to them: their groups with the
+    /// events in them, their participation in other groups, their friendships and their
+    /// calendar feed. Either everything is deleted or nothing is
+    pub fn delete(self, connection: &mut PgConnection) -> QueryResult<()> {
+        connection.transaction(|connection| {
+            let owned_groups = groups::table
+                .filter(groups::owner_id.eq(self.id))
+                .select(groups::id);
+            let owned_events = events::table
+                .filter(events::group_id.eq_any(owned_groups))
+                .select(events::id);
+
+            diesel::delete(
+                event_exceptions::table.filter(event_exceptions::event_id.eq_any(owned_events)),
+            )
+            .execute(connection)?;
+            diesel::delete(events::table.filter(events::group_id.eq_any(owned_groups)))
+                .execute(connection)?;
+            diesel::delete(
+                groups_participants::table.filter(
+                    groups_participants::group_id
+                        .eq_any(owned_groups)
+                        .or(groups_participants::participant_id.eq(self.id)),
+                ),
+            )
+            .execute(connection)?;
+            diesel::delete(groups::table.filter(groups::owner_id.eq(self.id)))
+                .execute(connection)?;
+            diesel::delete(
+                friendships::table.filter(
+                    friendships::owner_id
+                        .eq(self.id)
+                        .or(friendships::friend_id.eq(self.id)),
+                ),
+            )
+            .execute(connection)?;
+            diesel::delete(calendar_feeds::table.filter(calendar_feeds::user_id.eq(self.id)))
+                .execute(connection)?;
+            // The sessions, the two-factor secret, the recovery codes and the API tokens of the user
+            // are deleted with it
+            diesel::delete(users::table.find(self.id)).execute(connection)?;
+
+            Ok(())
+        })
     }
 }
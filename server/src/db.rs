@@ -1,7 +1,20 @@
+use std::time::Duration;
+
 use diesel::pg::PgConnection;
-use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError};
+
+use crate::config::DatabaseConfig;
+
+/// A pool of database connections that is shared by all the workers
+pub type DbPool = Pool<ConnectionManager<PgConnection>>;
 
-pub fn establish_connection(database_url: String) -> PgConnection {
-    PgConnection::establish(&database_url)
-        .unwrap_or_else(|_| panic!("Error connecting to {}", database_url))
+/// Create a pool of connections to the database in the configuration.
+/// It fails if the connections can not be opened in time
+pub fn create_pool(config: &DatabaseConfig) -> Result<DbPool, PoolError> {
+    Pool::builder()
+        .max_size(config.pool_size)
+        .connection_timeout(Duration::from_secs(config.pool_timeout))
+        // Check that a connection still works before giving it to a request
+        .test_on_check_out(true)
+        .build(ConnectionManager::new(&config.url))
 }
@@ -0,0 +1,83 @@
+use actix_web::{get, web::Json, Responder};
+
+use crate::{
+    config::ServerConfig,
+    data::{login_throttle::AccountLockout, session::AuthenticatedUser, users::User},
+    settings::ACCOUNT_LOCKOUTS_SHOWN,
+    ServerState,
+};
+
+use super::EndpointError;
+
+/// Check that the user is one of the admins in the configuration
+fn check_admin(config: &ServerConfig, user: &User) -> Result<(), EndpointError> {
+    if config.admins.contains(&user.id) {
+        Ok(())
+    } else {
+        Err(EndpointError::NotAllowed(
+            "Only admins can see the account lockouts.",
+        ))
+    }
+}
+
+/// An API endpoint used by the admins to see the latest accounts that were locked after too many
+/// failed logins, the newest first
+#[get("/api/get_account_lockouts")]
+pub async fn get_account_lockouts(
+    AuthenticatedUser(user): AuthenticatedUser,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    check_admin(&server_state.config.server, &user)?;
+
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            match AccountLockout::get_latest(connection, ACCOUNT_LOCKOUTS_SHOWN) {
+                Ok(lockouts) => Ok(Json(lockouts)),
+                Err(err) => {
+                    // Log the error
+                    log::error!("admin.get_account_lockouts.get: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i32, username: &str) -> User {
+        User {
+            id,
+            username: username.to_string(),
+            password_hash: String::new(),
+        }
+    }
+
+    fn config(admins: Vec<i32>) -> ServerConfig {
+        ServerConfig {
+            admins,
+            ..ServerConfig::default()
+        }
+    }
+
+    #[test]
+    fn allows_the_admins() {
+        assert!(check_admin(&config(vec![3, 7]), &user(7, "alice")).is_ok());
+    }
+
+    #[test]
+    fn rejects_users_that_only_share_the_username_of_an_admin() {
+        // E.g. someone who signed up with the name of a deleted admin account
+        let result = check_admin(&config(vec![7]), &user(8, "alice"));
+        assert!(matches!(result, Err(EndpointError::NotAllowed(_))));
+    }
+
+    #[test]
+    fn rejects_everyone_without_admins() {
+        let result = check_admin(&config(vec![]), &user(1, "admin"));
+        assert!(matches!(result, Err(EndpointError::NotAllowed(_))));
+    }
+}
@@ -0,0 +1,149 @@
+use actix_web::{get, post, web::Json, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{
+        api_tokens::{ApiToken, ApiTokenPublic, TokenScope, UnsavedApiToken},
+        models::UnsavedModel,
+        session::AuthenticatedUser,
+    },
+    settings::MAX_API_TOKEN_NAME_LENGTH,
+    ServerState,
+};
+
+use super::{
+    validation::{Validate, ValidationErrors},
+    EndpointError,
+};
+
+/// A struct for requests to create an API token
+#[derive(Deserialize)]
+pub struct CreateApiTokenRequest {
+    name: String,
+    scopes: Vec<TokenScope>,
+}
+
+impl Validate for CreateApiTokenRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check_text("name", "name", &self.name, MAX_API_TOKEN_NAME_LENGTH);
+        errors.check(
+            !self.scopes.is_empty(),
+            "scopes",
+            "Please choose what the token is allowed to do",
+        );
+    }
+}
+
+/// A struct for requests to revoke an API token
+#[derive(Deserialize)]
+pub struct RevokeApiTokenRequest {
+    token_id: i32,
+}
+
+impl Validate for RevokeApiTokenRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        // Ids start at 1, so other ones can not belong to a token
+        errors.check(
+            self.token_id > 0,
+            "token_id",
+            "Please choose a valid API token",
+        );
+    }
+}
+
+/// A struct used for responses with a new API token. The token itself is only shown this once
+#[derive(Serialize)]
+struct CreatedApiTokenResponse {
+    token: String,
+    #[serde(flatten)]
+    details: ApiTokenPublic,
+}
+
+/// An API endpoint used to get the API tokens of the user, without the tokens themselves
+#[get("/api/get_api_tokens")]
+pub async fn get_api_tokens(
+    AuthenticatedUser(user): AuthenticatedUser,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            match ApiToken::get_all_for_user(connection, user.id) {
+                Ok(tokens) => Ok(Json(
+                    tokens
+                        .into_iter()
+                        .map(ApiToken::to_public)
+                        .collect::<Vec<_>>(),
+                )),
+                Err(err) => {
+                    // Log the error
+                    log::error!("api_tokens.get_api_tokens.get: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
+
+/// An API endpoint used to create an API token that scripts can send in the
+/// `Authorization: Bearer` header instead of a session cookie
+#[post("/api/create_api_token")]
+pub async fn create_api_token(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<CreateApiTokenRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let CreateApiTokenRequest { name, mut scopes } = req_body.0;
+    // A scope only has to be stored once
+    scopes.sort();
+    scopes.dedup();
+
+    // Save the token on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let (unsaved_token, token) =
+                UnsavedApiToken::new(&user, name.trim().to_string(), scopes);
+
+            match unsaved_token.save(connection) {
+                Ok(api_token) => Ok(Json(CreatedApiTokenResponse {
+                    token,
+                    details: api_token.to_public(),
+                })),
+                Err(err) => {
+                    // Log the error
+                    log::error!("api_tokens.create_api_token.save: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
+
+/// An API endpoint used to revoke an API token, so that it stops working straight away
+#[post("/api/revoke_api_token")]
+pub async fn revoke_api_token(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<RevokeApiTokenRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let RevokeApiTokenRequest { token_id } = req_body.0;
+
+    // Delete the token on the thread pool for blocking operations
+    let deleted = server_state
+        .with_connection(move |connection| {
+            ApiToken::delete_for_user(connection, user.id, token_id).map_err(|err| {
+                // Log the error
+                log::error!("api_tokens.revoke_api_token.delete: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?;
+
+    if !deleted {
+        return Err(EndpointError::NotFound("This API token does not exist."));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
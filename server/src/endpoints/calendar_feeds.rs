@@ -0,0 +1,184 @@
+use actix_web::{
+    dev::ServiceRequest,
+    get, post,
+    web::{Json, Path},
+    HttpResponse, Responder,
+};
+use diesel::{PgConnection, QueryResult};
+use serde::Serialize;
+
+use crate::{
+    data::{
+        calendar_feeds::{CalendarFeed, UnsavedCalendarFeed},
+        event_exceptions::EventException,
+        events::Event,
+        group::Group,
+        icalendar::export_calendar,
+        models::UnsavedModel,
+        session::AuthenticatedUser,
+        users::User,
+    },
+    ServerState,
+};
+
+use super::EndpointError;
+
+/// A struct used for responses with the URL of a calendar feed
+#[derive(Serialize)]
+struct CalendarFeedUrlResponse {
+    url: String,
+}
+
+/// The start of the paths of the calendar feeds, which are followed by the secret token
+const FEED_PATH_PREFIX: &str = "/calendar/";
+
+/// Get the URL a calendar feed can be subscribed to at
+fn feed_url(public_url: &str, feed: &CalendarFeed) -> String {
+    format!("{public_url}{FEED_PATH_PREFIX}{}.ics", feed.token)
+}
+
+/// Get the request line of a request for the access log, e.g. `GET /api/get_friends HTTP/1.1`.
+/// The tokens of the calendar feeds are left out, as anyone who reads the log could use them to
+/// read the calendars
+pub fn logged_request_line(req: &ServiceRequest) -> String {
+    let path = match req.path().strip_prefix(FEED_PATH_PREFIX) {
+        Some(_) => format!("{FEED_PATH_PREFIX}[token].ics"),
+        None => req
+            .uri()
+            .path_and_query()
+            .map_or_else(|| req.path().to_string(), ToString::to_string),
+    };
+    format!("{} {} {:?}", req.method(), path, req.version())
+}
+
+/// An API endpoint used to get the secret URL of the user's calendar feed
+#[get("/api/get_calendar_feed_url")]
+pub async fn get_calendar_feed_url(
+    AuthenticatedUser(user): AuthenticatedUser,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    let public_url = server_state.config.public_url();
+
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            match CalendarFeed::get_or_create(connection, &user) {
+                Ok(feed) => Ok(Json(CalendarFeedUrlResponse {
+                    url: feed_url(&public_url, &feed),
+                })),
+                Err(err) => {
+                    // Log the error
+                    log::error!("calendar_feeds.get_calendar_feed_url.get: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
+
+/// An API endpoint used to replace the secret URL of the user's calendar feed, so that the old
+/// URL stops working
+#[post("/api/reset_calendar_feed_url")]
+pub async fn reset_calendar_feed_url(
+    AuthenticatedUser(user): AuthenticatedUser,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    let public_url = server_state.config.public_url();
+
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            match UnsavedCalendarFeed::new(&user).save(connection) {
+                Ok(feed) => Ok(Json(CalendarFeedUrlResponse {
+                    url: feed_url(&public_url, &feed),
+                })),
+                Err(err) => {
+                    // Log the error
+                    log::error!("calendar_feeds.reset_calendar_feed_url.save: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
+
+/// Build the calendar of a user
+fn build_calendar(connection: &mut PgConnection, user: &User, domain: &str) -> QueryResult<String> {
+    let events = Event::get_accepted_events_with_user(connection, user)?;
+
+    let event_ids: Vec<_> = events.iter().map(|event| event.id).collect();
+    let group_ids: Vec<_> = events.iter().map(|event| event.group_id).collect();
+    let exceptions = EventException::get_exceptions_for_events(connection, &event_ids)?;
+    let groups = Group::get_groups_by_ids(connection, &group_ids)?;
+
+    Ok(export_calendar(
+        &format!("{}'s calendar", user.username),
+        domain,
+        &events,
+        &groups,
+        &exceptions,
+    ))
+}
+
+/// An endpoint used by calendar clients to subscribe to a user's calendar.
+/// It does not use sessions, as the secret token in the URL identifies the user
+#[get("/calendar/{token}.ics")]
+pub async fn get_calendar_feed(
+    token: Path<String>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<HttpResponse, EndpointError> {
+    let domain = server_state.config.server.domain.clone();
+
+    // Query the database on the thread pool for blocking operations
+    let calendar = server_state
+        .with_connection(move |connection| {
+            let calendar = CalendarFeed::get_user_by_token(connection, &token).and_then(|user| {
+                user.map(|user| build_calendar(connection, &user, &domain))
+                    .transpose()
+            });
+
+            match calendar {
+                Err(err) => {
+                    // Log the error
+                    log::error!("calendar_feeds.get_calendar_feed.get: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+                Ok(None) => Err(EndpointError::NotFound("This calendar does not exist")),
+                Ok(Some(calendar)) => Ok(calendar),
+            }
+        })
+        .await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(calendar))
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::test::TestRequest;
+
+    use super::*;
+
+    #[test]
+    fn leaves_the_feed_tokens_out_of_the_log() {
+        let req = TestRequest::get()
+            .uri("/calendar/0123456789abcdef.ics?x=1")
+            .to_srv_request();
+        assert_eq!(
+            logged_request_line(&req),
+            "GET /calendar/[token].ics HTTP/1.1"
+        );
+    }
+
+    #[test]
+    fn logs_the_other_requests_in_full() {
+        let req = TestRequest::post()
+            .uri("/api/get_events?from=1&to=2")
+            .to_srv_request();
+        assert_eq!(
+            logged_request_line(&req),
+            "POST /api/get_events?from=1&to=2 HTTP/1.1"
+        );
+    }
+}
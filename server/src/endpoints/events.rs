@@ -1,48 +1,98 @@
-use actix_session::Session;
-use actix_web::{get, post, web::Json, Responder};
+use actix_web::{
+    get, post,
+    web::{Json, Query},
+    HttpResponse, Responder,
+};
+use chrono_tz::Tz;
+use diesel::{Connection, PgConnection, QueryResult};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     data::{
-        events::{Event, RecurrenceType, UnsavedEvent, VisibilityType},
+        event_exceptions::{EventException, UnsavedEventException},
+        events::{Event, UnsavedEvent, VisibilityType},
         group::Group,
+        icalendar::{import_calendar, SkippedComponent},
         models::UnsavedModel,
-        session::use_session,
+        recurrence::{expand_events, is_occurrence, EventOccurrence, RecurrenceRule},
+        session::AuthenticatedUser,
+        users::User,
     },
+    settings::{MAX_EVENTS_WINDOW, MAX_EVENT_TIME, MAX_EVENT_TITLE_LENGTH},
     ServerState,
 };
 
-use super::EndpointError;
+use super::{
+    validation::{Validate, ValidationErrors},
+    EndpointError,
+};
+
+/// A struct for get_events requests. The times are UNIX timestamps in seconds
+#[derive(Deserialize)]
+pub struct GetEventsRequest {
+    /// The beginning of the time window, inclusive
+    from: i64,
+    /// The end of the time window, exclusive
+    to: i64,
+}
+
+impl Validate for GetEventsRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        // Check that the time window makes sense
+        if self.from >= self.to {
+            errors.add(
+                "to",
+                "The end of the time window has to be after its beginning",
+            );
+        } else if self.to.saturating_sub(self.from) > MAX_EVENTS_WINDOW {
+            errors.add(
+                "to",
+                "The time window is too long. Please request fewer events at once",
+            );
+        }
+    }
+}
 
 /// A struct used for a `get_events` response
 #[derive(Serialize)]
 struct GetEventsResponse {
-    events: Vec<Event>,
+    events: Vec<EventOccurrence>,
 }
-/// An API endpoint used to get events a user needs to attend
+/// An API endpoint used to get the occurrences of events a user needs to attend within a time
+/// window
 #[get("/api/get_events")]
 pub async fn get_events(
-    session: Session,
+    AuthenticatedUser(user): AuthenticatedUser,
+    query: Query<GetEventsRequest>,
     server_state: actix_web::web::Data<ServerState>,
 ) -> Result<impl Responder, EndpointError> {
-    use_session!(session, user);
+    query.validate()?;
+    let GetEventsRequest { from, to } = query.0;
 
-    // Get the connection from the mutex
-    let mut connection = server_state
-        .connection
-        .lock()
-        .expect("Could not get the connection from ServerState");
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            // Get the events together with the changes to their occurrences
+            let events =
+                Event::get_accepted_events_with_user(connection, &user).and_then(|events| {
+                    let event_ids: Vec<_> = events.iter().map(|event| event.id).collect();
+                    let exceptions =
+                        EventException::get_exceptions_for_events(connection, &event_ids)?;
+                    Ok((events, exceptions))
+                });
 
-    let events = Event::get_accepted_events_with_user(&mut connection, &user);
-
-    match events {
-        Ok(events) => Ok(Json(GetEventsResponse { events })),
-        Err(err) => {
-            // Log the error
-            log::error!("events.get_events.get: {}", err);
-            Result::Err(EndpointError::InternalError)
-        }
-    }
+            match events {
+                Ok((events, exceptions)) => Ok(Json(GetEventsResponse {
+                    events: expand_events(&events, &exceptions, from, to),
+                })),
+                Err(err) => {
+                    // Log the error
+                    log::error!("events.get_events.get: {}", err);
+                    Result::Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
 }
 
 /// A struct for create_event requests
@@ -50,73 +100,650 @@ pub async fn get_events(
 pub struct CreateEventRequest {
     pub title: String,
     pub visibility: VisibilityType,
-    pub start_time: i32,
-    pub duration: i32,
-    pub recurrence: RecurrenceType,
+    pub start_time: i64,
+    pub duration: i64,
+    pub recurrence: RecurrenceRule,
     pub group_id: i32,
+    /// The IANA name of the time zone the event repeats in
+    #[serde(default = "default_time_zone")]
+    pub time_zone: String,
+}
+
+/// The time zone used by clients that do not send one
+fn default_time_zone() -> String {
+    String::from("UTC")
+}
+
+/// Check the name of a time zone and a recurrence rule of an event that starts at `start_time`
+fn check_timing(
+    errors: &mut ValidationErrors,
+    recurrence: &RecurrenceRule,
+    start_time: i64,
+    time_zone: &str,
+) {
+    match time_zone.parse::<Tz>() {
+        Ok(time_zone) => {
+            if let Err(message) = recurrence.validate(start_time, time_zone) {
+                errors.add("recurrence", message);
+            }
+        }
+        Err(_) => errors.add("time_zone", "This time zone does not exist"),
+    }
+}
+
+impl Validate for CreateEventRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check_text("title", "title", &self.title, MAX_EVENT_TITLE_LENGTH);
+        check_duration(errors, "duration", self.start_time, self.duration);
+        check_timing(errors, &self.recurrence, self.start_time, &self.time_zone);
+    }
 }
 
 /// An API endpoint used to create an event
 #[post("/api/create_event")]
 pub async fn create_event(
-    session: Session,
+    AuthenticatedUser(user): AuthenticatedUser,
     req_body: Json<CreateEventRequest>,
     server_state: actix_web::web::Data<ServerState>,
-) -> Result<&'static str, EndpointError> {
-    use_session!(session, user);
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
 
-    // Get the connection from the mutex
-    let mut connection = server_state
-        .connection
-        .lock()
-        .expect("Could not get the connection from ServerState");
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let CreateEventRequest {
+                title,
+                visibility,
+                recurrence,
+                start_time,
+                duration,
+                group_id,
+                time_zone,
+            } = req_body.0;
 
-    let CreateEventRequest {
-        title,
-        visibility,
-        recurrence,
-        start_time,
-        duration,
-        group_id,
-    } = req_body.0;
+            // check that the user has admin rights over the group
+            let group = Group::get_group_by_id(connection, group_id);
+            match group {
+                Err(err) => {
+                    // log the error
+                    log::error!("events.create_event.find_group: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+                // If the group was not found
+                Ok(None) => Err(EndpointError::NotFound("This group does not exist")),
+                Ok(Some(group)) => {
+                    // If the user is not the owner
+                    if group.owner_id != user.id {
+                        return Err(EndpointError::NotAllowed(
+                    "You are not the group owner and so do not have the permission to create events.",
+                ));
+                    }
+
+                    let event = UnsavedEvent {
+                        title,
+                        visibility,
+                        recurrence,
+                        start_time,
+                        duration,
+                        group_id,
+                        time_zone,
+                    };
 
-    // check that the user has admin rights over the group
-    let group = Group::get_group_by_id(&mut connection, group_id);
-    match group {
+                    match event.save(connection) {
+                        Ok(event) => Ok(Json(event)),
+                        Err(err) => {
+                            // Generic error
+
+                            // Log the error
+                            log::error!("events.create_event.save: {}", err);
+                            Result::Err(EndpointError::InternalError)
+                        }
+                    }
+                }
+            }
+        })
+        .await
+}
+
+/// Find an event and check that the user has admin rights over the group it belongs to
+/// `endpoint_name` is used to log errors
+fn find_owned_event(
+    connection: &mut PgConnection,
+    user: &User,
+    event_id: i32,
+    endpoint_name: &str,
+) -> Result<Event, EndpointError> {
+    let event = Event::get_event_by_id(connection, event_id).and_then(|event| match event {
+        Some(event) => {
+            let group = Group::get_group_by_id(connection, event.group_id)?;
+            Ok(Some((event, group)))
+        }
+        None => Ok(None),
+    });
+
+    match event {
         Err(err) => {
             // log the error
-            log::error!("events.create_event.find_group: {}", err);
+            log::error!("events.{}.find_event: {}", endpoint_name, err);
             Err(EndpointError::InternalError)
         }
-        // If the group was not found
-        Ok(None) => Err(EndpointError::BadClientData("This group does not exist")),
-        Ok(Some(group)) => {
+        // If the event was not found
+        Ok(None) => Err(EndpointError::NotFound("This event does not exist")),
+        Ok(Some((event, group))) => {
             // If the user is not the owner
-            if group.owner_id != user.id {
-                return Err(EndpointError::BadClientData(
-            "You are not the group owner and so do not have the permission to create events.",
-        ));
+            if group.map(|group| group.owner_id) != Some(user.id) {
+                return Err(EndpointError::NotAllowed(
+                    "You are not the group owner and so do not have the permission to change this event.",
+                ));
+            }
+            Ok(event)
+        }
+    }
+}
+
+/// Check that a time an occurrence of an event starts at is not before 1970 or after the year 9999
+fn check_event_time(errors: &mut ValidationErrors, field: &'static str, time: i64) {
+    errors.check(
+        (0..=MAX_EVENT_TIME).contains(&time),
+        field,
+        "The time has to be between the years 1970 and 9999",
+    );
+}
+
+/// Check that an event that starts at `start_time` lasts for some time, and ends before the year
+/// 10000 so that its end can be calculated without overflowing
+fn check_duration(
+    errors: &mut ValidationErrors,
+    field: &'static str,
+    start_time: i64,
+    duration: i64,
+) {
+    if duration <= 0 {
+        errors.add(field, "The event has to last for some time");
+    } else {
+        errors.check(
+            duration <= MAX_EVENT_TIME.saturating_sub(start_time),
+            field,
+            "The event has to end before the year 10000",
+        );
+    }
+}
+
+/// Check that the event has an occurrence at `time`, reporting an error in `field` otherwise
+fn check_is_occurrence(event: &Event, field: &'static str, time: i64) -> Result<(), EndpointError> {
+    let mut errors = ValidationErrors::default();
+    errors.check(
+        is_occurrence(event, time),
+        field,
+        "The event does not happen at this time",
+    );
+    errors.into_result()
+}
+
+/// A struct for cancel_event_occurrence requests
+#[derive(Deserialize)]
+pub struct CancelEventOccurrenceRequest {
+    event_id: i32,
+    /// The time the occurrence was originally scheduled for
+    original_start_time: i64,
+}
+
+impl Validate for CancelEventOccurrenceRequest {
+    // Whether the event happens at the time is checked against the event it belongs to
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        check_event_time(errors, "original_start_time", self.original_start_time);
+    }
+}
+
+/// An API endpoint used to cancel a single occurrence of an event
+#[post("/api/cancel_event_occurrence")]
+pub async fn cancel_event_occurrence(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<CancelEventOccurrenceRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let CancelEventOccurrenceRequest {
+        event_id,
+        original_start_time,
+    } = req_body.0;
+
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let event = find_owned_event(connection, &user, event_id, "cancel_event_occurrence")?;
+
+            check_is_occurrence(&event, "original_start_time", original_start_time)?;
+
+            let exception = UnsavedEventException {
+                event_id,
+                original_start_time,
+                is_cancelled: true,
+                new_start_time: None,
+                new_duration: None,
+            };
+
+            match exception.save(connection) {
+                Ok(exception) => Ok(Json(exception)),
+                Err(err) => {
+                    // Log the error
+                    log::error!("events.cancel_event_occurrence.save: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
+
+/// A struct for reschedule_event_occurrence requests
+#[derive(Deserialize)]
+pub struct RescheduleEventOccurrenceRequest {
+    event_id: i32,
+    /// The time the occurrence was originally scheduled for
+    original_start_time: i64,
+    new_start_time: i64,
+    new_duration: i64,
+}
+
+impl Validate for RescheduleEventOccurrenceRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        check_event_time(errors, "original_start_time", self.original_start_time);
+        check_event_time(errors, "new_start_time", self.new_start_time);
+        check_duration(
+            errors,
+            "new_duration",
+            self.new_start_time,
+            self.new_duration,
+        );
+    }
+}
+
+/// An API endpoint used to move a single occurrence of an event to a different time
+#[post("/api/reschedule_event_occurrence")]
+pub async fn reschedule_event_occurrence(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<RescheduleEventOccurrenceRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let RescheduleEventOccurrenceRequest {
+        event_id,
+        original_start_time,
+        new_start_time,
+        new_duration,
+    } = req_body.0;
+
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let event =
+                find_owned_event(connection, &user, event_id, "reschedule_event_occurrence")?;
+
+            check_is_occurrence(&event, "original_start_time", original_start_time)?;
+
+            let exception = UnsavedEventException {
+                event_id,
+                original_start_time,
+                is_cancelled: false,
+                new_start_time: Some(new_start_time),
+                new_duration: Some(new_duration),
+            };
+
+            match exception.save(connection) {
+                Ok(exception) => Ok(Json(exception)),
+                Err(err) => {
+                    // Log the error
+                    log::error!("events.reschedule_event_occurrence.save: {}", err);
+                    Err(EndpointError::InternalError)
+                }
             }
+        })
+        .await
+}
+
+/// Which occurrences of an event a change applies to
+#[derive(Deserialize, PartialEq, Eq)]
+pub enum ChangeScope {
+    ThisOccurrence,
+    ThisAndFollowing,
+    All,
+}
+
+/// Check that an occurrence was chosen if the change does not apply to all the occurrences
+fn check_occurrence_chosen(
+    errors: &mut ValidationErrors,
+    scope: &ChangeScope,
+    occurrence_start_time: Option<i64>,
+) {
+    errors.check(
+        *scope == ChangeScope::All || occurrence_start_time.is_some(),
+        "occurrence_start_time",
+        "Please choose the occurrence of the event to change",
+    );
+}
+
+/// Get the chosen occurrence of an event, checking that the event actually happens at that time
+fn get_chosen_occurrence(
+    event: &Event,
+    occurrence_start_time: Option<i64>,
+) -> Result<i64, EndpointError> {
+    let occurrence_start_time = occurrence_start_time.ok_or(EndpointError::BadClientData(
+        "Please choose the occurrence of the event to change",
+    ))?;
+
+    check_is_occurrence(event, "occurrence_start_time", occurrence_start_time)?;
+    Ok(occurrence_start_time)
+}
+
+/// A struct for update_event requests
+#[derive(Deserialize)]
+pub struct UpdateEventRequest {
+    event_id: i32,
+    scope: ChangeScope,
+    /// The original start time of the chosen occurrence. It is not needed if all the occurrences
+    /// are changed
+    occurrence_start_time: Option<i64>,
+    title: String,
+    visibility: VisibilityType,
+    start_time: i64,
+    duration: i64,
+    /// The new recurrence rule. It is ignored when only one occurrence is changed
+    recurrence: RecurrenceRule,
+    /// The IANA name of the time zone the event repeats in
+    #[serde(default = "default_time_zone")]
+    time_zone: String,
+}
+
+impl Validate for UpdateEventRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        check_occurrence_chosen(errors, &self.scope, self.occurrence_start_time);
+        errors.check_text("title", "title", &self.title, MAX_EVENT_TITLE_LENGTH);
+        check_duration(errors, "duration", self.start_time, self.duration);
+        // A single occurrence does not repeat, so its recurrence rule is ignored
+        let recurrence = if self.scope == ChangeScope::ThisOccurrence {
+            RecurrenceRule::once()
+        } else {
+            self.recurrence.clone()
+        };
+        check_timing(errors, &recurrence, self.start_time, &self.time_zone);
+    }
+}
 
-            let event = UnsavedEvent {
+/// An API endpoint used to change an event or some of its occurrences.
+/// It responds with the changed event, or with the new event that the changed occurrences were
+/// moved to
+#[post("/api/update_event")]
+pub async fn update_event(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<UpdateEventRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let UpdateEventRequest {
+        event_id,
+        scope,
+        occurrence_start_time,
+        title,
+        visibility,
+        start_time,
+        duration,
+        recurrence,
+        time_zone,
+    } = req_body.0;
+
+    // A single occurrence does not repeat
+    let recurrence = if scope == ChangeScope::ThisOccurrence {
+        RecurrenceRule::once()
+    } else {
+        recurrence
+    };
+
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let event = find_owned_event(connection, &user, event_id, "update_event")?;
+
+            // Find the chosen occurrence, unless all the occurrences are changed
+            let occurrence = match scope {
+                ChangeScope::All => None,
+                _ => Some(get_chosen_occurrence(&event, occurrence_start_time)?),
+            };
+            // Changing the first occurrence and the ones following it changes all the occurrences
+            let scope =
+                if scope == ChangeScope::ThisAndFollowing && occurrence == Some(event.start_time) {
+                    ChangeScope::All
+                } else {
+                    scope
+                };
+
+            let changes = UnsavedEvent {
                 title,
                 visibility,
-                recurrence,
                 start_time,
                 duration,
-                group_id,
+                group_id: event.group_id,
+                recurrence,
+                time_zone,
+            };
+
+            let result = match (scope, occurrence) {
+                (ChangeScope::All, _) | (_, None) => Event::update(connection, &event, changes),
+                (scope, Some(occurrence)) => {
+                    // Detach the changed occurrences from the event into a new event
+                    connection.transaction(|connection| {
+                        if scope == ChangeScope::ThisOccurrence {
+                            // Cancel the occurrence
+                            UnsavedEventException {
+                                event_id,
+                                original_start_time: occurrence,
+                                is_cancelled: true,
+                                new_start_time: None,
+                                new_duration: None,
+                            }
+                            .save(connection)?;
+                        } else {
+                            Event::end_before(connection, &event, occurrence)?;
+                        }
+
+                        changes.save(connection)
+                    })
+                }
             };
 
-            match event.save(&mut connection) {
-                Ok(_) => Ok("Success!"),
+            match result {
+                Ok(event) => Ok(Json(event)),
                 Err(err) => {
-                    // Generic error
+                    // Log the error
+                    log::error!("events.update_event.save: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
 
+/// A struct for delete_event requests
+#[derive(Deserialize)]
+pub struct DeleteEventRequest {
+    event_id: i32,
+    scope: ChangeScope,
+    /// The original start time of the chosen occurrence. It is not needed if all the occurrences
+    /// are deleted
+    occurrence_start_time: Option<i64>,
+}
+
+impl Validate for DeleteEventRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        check_occurrence_chosen(errors, &self.scope, self.occurrence_start_time);
+    }
+}
+
+/// An API endpoint used to delete an event or some of its occurrences
+#[post("/api/delete_event")]
+pub async fn delete_event(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<DeleteEventRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let DeleteEventRequest {
+        event_id,
+        scope,
+        occurrence_start_time,
+    } = req_body.0;
+
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let event = find_owned_event(connection, &user, event_id, "delete_event")?;
+
+            let result = match scope {
+                ChangeScope::All => Event::delete(connection, event_id).map(|_| ()),
+                ChangeScope::ThisOccurrence => {
+                    let occurrence = get_chosen_occurrence(&event, occurrence_start_time)?;
+                    UnsavedEventException {
+                        event_id,
+                        original_start_time: occurrence,
+                        is_cancelled: true,
+                        new_start_time: None,
+                        new_duration: None,
+                    }
+                    .save(connection)
+                    .map(|_| ())
+                }
+                ChangeScope::ThisAndFollowing => {
+                    let occurrence = get_chosen_occurrence(&event, occurrence_start_time)?;
+                    // Deleting the first occurrence and the ones following it deletes the whole event
+                    if occurrence == event.start_time {
+                        Event::delete(connection, event_id).map(|_| ())
+                    } else {
+                        Event::end_before(connection, &event, occurrence).map(|_| ())
+                    }
+                }
+            };
+
+            match result {
+                Ok(_) => Ok(()),
+                Err(err) => {
                     // Log the error
-                    log::error!("events.create_event.save: {}", err);
-                    Result::Err(EndpointError::InternalError)
+                    log::error!("events.delete_event.delete: {}", err);
+                    Err(EndpointError::InternalError)
                 }
             }
-        }
+        })
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// A struct for import_calendar requests
+#[derive(Deserialize)]
+pub struct ImportCalendarRequest {
+    group_id: i32,
+    /// The text of an iCalendar (.ics) file
+    calendar: String,
+    /// The IANA name of the time zone used for the times in the file that do not have one
+    #[serde(default = "default_time_zone")]
+    time_zone: String,
+}
+
+impl Validate for ImportCalendarRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            !self.calendar.trim().is_empty(),
+            "calendar",
+            "The calendar file is empty",
+        );
+        errors.check(
+            self.time_zone.parse::<Tz>().is_ok(),
+            "time_zone",
+            "This time zone does not exist",
+        );
     }
 }
+
+/// A struct used for an `import_calendar` response
+#[derive(Serialize)]
+struct ImportCalendarResponse {
+    /// The number of events that were created
+    imported: usize,
+    skipped: Vec<SkippedComponent>,
+}
+
+/// An API endpoint used to create events in a group from an iCalendar file, at
+/// `/api/import_calendar`. It is registered with a limit on the size of the file
+pub async fn import_calendar_events(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<ImportCalendarRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let ImportCalendarRequest {
+        group_id,
+        calendar,
+        time_zone,
+    } = req_body.0;
+
+    let time_zone: Tz = time_zone
+        .parse()
+        .map_err(|_| EndpointError::BadClientData("This time zone does not exist"))?;
+    // Parse the file and query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            // check that the user has admin rights over the group
+            match Group::get_group_by_id(connection, group_id) {
+                Err(err) => {
+                    // log the error
+                    log::error!("events.import_calendar.find_group: {}", err);
+                    return Err(EndpointError::InternalError);
+                }
+                // If the group was not found
+                Ok(None) => return Err(EndpointError::NotFound("This group does not exist")),
+                Ok(Some(group)) => {
+                    // If the user is not the owner
+                    if group.owner_id != user.id {
+                        return Err(EndpointError::NotAllowed(
+                            "You are not the group owner and so do not have the permission to create events.",
+                        ));
+                    }
+                }
+            }
+
+            let calendar = import_calendar(&calendar, group_id, time_zone)
+                .map_err(EndpointError::BadClientData)?;
+            let imported = calendar.events.len();
+
+            // Save all the events or none of them
+            let result = connection.transaction(|connection| {
+                for imported_event in calendar.events {
+                    let event = imported_event.event.save(connection)?;
+
+                    // Cancel the occurrences that were excluded
+                    for original_start_time in imported_event.excluded_start_times {
+                        UnsavedEventException {
+                            event_id: event.id,
+                            original_start_time,
+                            is_cancelled: true,
+                            new_start_time: None,
+                            new_duration: None,
+                        }
+                        .save(connection)?;
+                    }
+                }
+                QueryResult::Ok(())
+            });
+
+            match result {
+                Ok(_) => Ok(Json(ImportCalendarResponse {
+                    imported,
+                    skipped: calendar.skipped,
+                })),
+                Err(err) => {
+                    // Log the error
+                    log::error!("events.import_calendar.save: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
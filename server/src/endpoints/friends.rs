@@ -1,17 +1,20 @@
-use actix_session::Session;
-use actix_web::{get, post, web::Json, Responder};
+use actix_web::{get, post, web::Json, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     data::{
-        friends::{FriendAddResult, Friendship},
-        session::use_session,
+        friends::{FriendAddResult, FriendRequests, Friendship, FriendshipStatus},
+        session::AuthenticatedUser,
         users::{User, UserPublic},
     },
+    settings::MAX_USERNAME_LENGTH,
     ServerState,
 };
 
-use super::EndpointError;
+use super::{
+    validation::{Validate, ValidationErrors},
+    EndpointError,
+};
 
 /// A struct to represent responses to get_friends requests
 #[derive(Serialize)]
@@ -23,67 +26,230 @@ pub struct AddFriendRequest {
     username: String,
 }
 
+impl Validate for AddFriendRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check_text("username", "username", &self.username, MAX_USERNAME_LENGTH);
+    }
+}
+
+/// A struct to represent responses to add_friend requests, with whether the friend still has to
+/// accept the request
+#[derive(Serialize)]
+struct AddFriendResponse {
+    #[serde(flatten)]
+    friend: UserPublic,
+    status: FriendshipStatus,
+}
+
+/// A struct to represent responses to get_friend_requests requests
+#[derive(Serialize)]
+struct GetFriendRequestsResponse {
+    incoming: Vec<UserPublic>,
+    outgoing: Vec<UserPublic>,
+}
+
+/// A struct to represent requests to accept, decline or cancel the friend request between the
+/// user and another user
+#[derive(Deserialize)]
+pub struct FriendRequestUserRequest {
+    user_id: i32,
+}
+
+impl Validate for FriendRequestUserRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        // Ids start at 1, so other ones can not belong to a user
+        errors.check(self.user_id > 0, "user_id", "Please choose a valid user");
+    }
+}
+
+/// An API endpoint used to get the friends of the user, i.e. the accepted friendships
 #[get("/api/get_friends")]
 pub async fn get_friends(
-    session: Session,
+    AuthenticatedUser(user): AuthenticatedUser,
     server_state: actix_web::web::Data<ServerState>,
 ) -> Result<impl Responder, EndpointError> {
-    use_session!(session, user);
-    // Get the connection from the mutex
-    let mut connection = server_state
-        .connection
-        .lock()
-        .expect("Could not get the connection from ServerState");
-
-    let friends = Friendship::get_friends(&mut connection, &user);
-
-    match friends {
-        Ok(friends) => {
-            // make it safe to send to the frontend
-            let friends: Vec<_> = friends.into_iter().map(User::to_public).collect();
-            Ok(Json(GetFriendsResponse(friends)))
-        }
-        Err(err) => {
-            // log the error
-            log::error!("friendships.get_friends.get: {}", err);
-            Result::Err(EndpointError::InternalError)
-        }
-    }
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let friends = Friendship::get_friends(connection, &user);
+
+            match friends {
+                Ok(friends) => {
+                    // make it safe to send to the frontend
+                    let friends: Vec<_> = friends.into_iter().map(User::to_public).collect();
+                    Ok(Json(GetFriendsResponse(friends)))
+                }
+                Err(err) => {
+                    // log the error
+                    log::error!("friendships.get_friends.get: {}", err);
+                    Result::Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
 }
 
+/// An API endpoint used to send a friend request to a user by their username
 #[post("/api/add_friend")]
 pub async fn add_friend(
-    session: Session,
+    AuthenticatedUser(user): AuthenticatedUser,
     req_body: Json<AddFriendRequest>,
     server_state: actix_web::web::Data<ServerState>,
-) -> Result<&'static str, EndpointError> {
-    use_session!(session, user);
-    // Get the connection from the mutex
-    let mut connection = server_state
-        .connection
-        .lock()
-        .expect("Could not get the connection from ServerState");
-
-    let friend_username = req_body.username.clone();
-
-    let result = Friendship::add_friend(&mut connection, &user, &friend_username);
-    match result {
-        Err(err) => {
-            // Log the error
-            log::error!("friendships.add_friend.save: {}", err);
-            Result::Err(EndpointError::InternalError)
-        }
-        Ok(result) => match result {
-            FriendAddResult::TriedFriendThemselves => Err(EndpointError::BadClientData(
-                "You can not add yourself as a friend",
-            )),
-            FriendAddResult::AlreadyFriends => Err(EndpointError::BadClientData(
-                "You are already friends with this user.",
-            )),
-            FriendAddResult::UsernameNotFound => {
-                Err(EndpointError::BadClientData("This user does not exist."))
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let friend_username = req_body.username.clone();
+
+            let result = Friendship::add_friend(connection, &user, &friend_username);
+            match result {
+                Err(err) => {
+                    // Log the error
+                    log::error!("friendships.add_friend.save: {}", err);
+                    Result::Err(EndpointError::InternalError)
+                }
+                Ok(result) => match result {
+                    FriendAddResult::TriedFriendThemselves => Err(EndpointError::BadClientData(
+                        "You can not add yourself as a friend",
+                    )),
+                    FriendAddResult::AlreadyFriends => Err(EndpointError::Conflict(
+                        "You are already friends with this user.",
+                    )),
+                    FriendAddResult::AlreadyRequested => Err(EndpointError::Conflict(
+                        "You have already sent a friend request to this user.",
+                    )),
+                    FriendAddResult::UsernameNotFound => {
+                        Err(EndpointError::NotFound("This user does not exist."))
+                    }
+                    FriendAddResult::RequestSent(friend) => Ok(Json(AddFriendResponse {
+                        friend: friend.to_public(),
+                        status: FriendshipStatus::Pending,
+                    })),
+                    FriendAddResult::RequestAccepted(friend) => Ok(Json(AddFriendResponse {
+                        friend: friend.to_public(),
+                        status: FriendshipStatus::Accepted,
+                    })),
+                },
             }
-            FriendAddResult::Success => Ok("Success!"),
-        },
+        })
+        .await
+}
+
+/// An API endpoint used to get the friend requests the user has sent and received, which have not
+/// been answered yet
+#[get("/api/get_friend_requests")]
+pub async fn get_friend_requests(
+    AuthenticatedUser(user): AuthenticatedUser,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            match Friendship::get_requests(connection, &user) {
+                Ok(FriendRequests { incoming, outgoing }) => {
+                    // make it safe to send to the frontend
+                    Ok(Json(GetFriendRequestsResponse {
+                        incoming: incoming.into_iter().map(User::to_public).collect(),
+                        outgoing: outgoing.into_iter().map(User::to_public).collect(),
+                    }))
+                }
+                Err(err) => {
+                    // log the error
+                    log::error!("friendships.get_friend_requests.get: {}", err);
+                    Err(EndpointError::InternalError)
+                }
+            }
+        })
+        .await
+}
+
+/// An API endpoint used to accept a friend request from another user
+#[post("/api/accept_friend_request")]
+pub async fn accept_friend_request(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<FriendRequestUserRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let FriendRequestUserRequest { user_id } = req_body.0;
+
+    // Update the request on the thread pool for blocking operations
+    let accepted = server_state
+        .with_connection(move |connection| {
+            Friendship::accept_request(connection, &user, user_id).map_err(|err| {
+                // Log the error
+                log::error!("friendships.accept_friend_request.update: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?;
+
+    if !accepted {
+        return Err(EndpointError::NotFound(
+            "This friend request does not exist.",
+        ));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// An API endpoint used to decline a friend request from another user
+#[post("/api/decline_friend_request")]
+pub async fn decline_friend_request(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<FriendRequestUserRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let FriendRequestUserRequest { user_id } = req_body.0;
+
+    // Delete the request on the thread pool for blocking operations
+    let declined = server_state
+        .with_connection(move |connection| {
+            Friendship::decline_request(connection, &user, user_id).map_err(|err| {
+                // Log the error
+                log::error!("friendships.decline_friend_request.delete: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?;
+
+    if !declined {
+        return Err(EndpointError::NotFound(
+            "This friend request does not exist.",
+        ));
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// An API endpoint used to cancel a friend request the user has sent, before it is answered
+#[post("/api/cancel_friend_request")]
+pub async fn cancel_friend_request(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<FriendRequestUserRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let FriendRequestUserRequest { user_id } = req_body.0;
+
+    // Delete the request on the thread pool for blocking operations
+    let cancelled = server_state
+        .with_connection(move |connection| {
+            Friendship::cancel_request(connection, &user, user_id).map_err(|err| {
+                // Log the error
+                log::error!("friendships.cancel_friend_request.delete: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?;
+
+    if !cancelled {
+        return Err(EndpointError::NotFound(
+            "This friend request does not exist.",
+        ));
     }
+
+    Ok(HttpResponse::NoContent().finish())
 }
@@ -1,10 +1,22 @@
+pub mod admin;
+pub mod api_tokens;
+pub mod calendar_feeds;
 pub mod events;
 pub mod friends;
 pub mod groups;
 pub mod notifications;
+pub mod two_factor;
 pub mod users;
-use actix_web::{error, http::StatusCode, HttpResponse};
+pub mod validation;
+use actix_web::{
+    error,
+    http::{header::RETRY_AFTER, StatusCode},
+    HttpRequest, HttpResponse,
+};
 use derive_more::Display;
+use serde::Serialize;
+
+use self::validation::{FieldError, ValidationErrors};
 
 /// An enum that represents an error in the endpoint
 #[derive(Debug, Display)]
@@ -12,20 +24,111 @@ pub enum EndpointError {
     #[display(fmt = "Internal error. Please try again later.")]
     InternalError,
 
-    #[display(fmt = "Bad request. {}", _0)]
+    /// No database connection became free in time
+    #[display(fmt = "The server is busy. Please try again later.")]
+    Unavailable,
+
+    #[display(fmt = "Could not authenticate you. Please go to the login page.")]
+    NotLoggedIn,
+
+    #[display(fmt = "{}", _0)]
     BadClientData(&'static str),
+
+    /// The body or the query of the request could not be read
+    #[display(fmt = "{}", _0)]
+    MalformedRequest(String),
+
+    #[display(fmt = "{}", _0)]
+    InvalidFields(ValidationErrors),
+
+    /// The resource the request refers to does not exist
+    #[display(fmt = "{}", _0)]
+    NotFound(&'static str),
+
+    /// The user is not allowed to make the request
+    #[display(fmt = "{}", _0)]
+    NotAllowed(&'static str),
+
+    /// The request conflicts with the existing data, e.g. a username is taken
+    #[display(fmt = "{}", _0)]
+    Conflict(&'static str),
+
+    /// The client has to wait for this many seconds before trying again
+    #[display(fmt = "Too many failed attempts. Please try again in {} seconds.", _0)]
+    TooManyRequests(i64),
+}
+
+/// A struct used for the body of error responses
+#[derive(Serialize)]
+struct ErrorResponse<'a> {
+    /// A stable, machine-readable code for the kind of the error
+    code: &'static str,
+    /// A message that can be shown to the user
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fields: Option<&'a [FieldError]>,
+}
+
+impl EndpointError {
+    /// Get the code that identifies the kind of the error
+    pub fn code(&self) -> &'static str {
+        match self {
+            EndpointError::InternalError => "internal_error",
+            EndpointError::Unavailable => "unavailable",
+            EndpointError::NotLoggedIn => "not_logged_in",
+            EndpointError::BadClientData(_) => "bad_request",
+            EndpointError::MalformedRequest(_) => "malformed_request",
+            EndpointError::InvalidFields(_) => "invalid_fields",
+            EndpointError::NotFound(_) => "not_found",
+            EndpointError::NotAllowed(_) => "not_allowed",
+            EndpointError::Conflict(_) => "conflict",
+            EndpointError::TooManyRequests(_) => "too_many_requests",
+        }
+    }
 }
 
 impl error::ResponseError for EndpointError {
     /// Formulating an HttpResponse based on the error
     fn error_response(&self) -> HttpResponse {
-        HttpResponse::build(self.status_code()).body(self.to_string())
+        let fields = match self {
+            EndpointError::InvalidFields(errors) => Some(errors.0.as_slice()),
+            _ => None,
+        };
+
+        let mut response = HttpResponse::build(self.status_code());
+        // Tell the client when it can try again
+        if let EndpointError::TooManyRequests(retry_after) = self {
+            response.insert_header((RETRY_AFTER, retry_after.to_string()));
+        }
+
+        response.json(ErrorResponse {
+            code: self.code(),
+            message: self.to_string(),
+            fields,
+        })
     }
 
     fn status_code(&self) -> StatusCode {
         match *self {
             EndpointError::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
-            EndpointError::BadClientData(_) => StatusCode::BAD_REQUEST,
+            EndpointError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            EndpointError::NotLoggedIn => StatusCode::UNAUTHORIZED,
+            EndpointError::BadClientData(_)
+            | EndpointError::MalformedRequest(_)
+            | EndpointError::InvalidFields(_) => StatusCode::BAD_REQUEST,
+            EndpointError::NotFound(_) => StatusCode::NOT_FOUND,
+            EndpointError::NotAllowed(_) => StatusCode::FORBIDDEN,
+            EndpointError::Conflict(_) => StatusCode::CONFLICT,
+            EndpointError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 }
+
+/// Turn the errors from reading the body or the query of a request into endpoint errors, so that
+/// they have the same format as the rest
+pub fn malformed_request_handler(
+    err: impl std::fmt::Display,
+    _req: &HttpRequest,
+) -> actix_web::Error {
+    EndpointError::MalformedRequest(err.to_string()).into()
+}
@@ -0,0 +1,297 @@
+use actix_session::Session;
+use actix_web::{post, web::Json, HttpRequest, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::{
+        login_throttle::LoginThrottle,
+        models::UnsavedModel,
+        session::{get_pending_two_factor, set_session, AuthenticatedUser},
+        two_factor::{RecoveryCode, TwoFactorSecret, UnsavedTwoFactorSecret},
+        users::{UnsavedUser, User},
+    },
+    proxy::ClientIp,
+    ServerState,
+};
+
+use super::{
+    users::{check_password_throttled, record_login_failure, start_session},
+    validation::{Validate, ValidationErrors},
+    EndpointError,
+};
+
+/// A struct used for responses with what an authenticator app has to be set up with
+#[derive(Serialize)]
+struct TwoFactorSetupResponse {
+    /// The secret, for apps that it has to be typed into
+    secret: String,
+    /// The URI that apps can be set up with by scanning it as a QR code
+    otpauth_uri: String,
+}
+
+/// A struct used for responses with new recovery codes
+#[derive(Serialize)]
+struct RecoveryCodesResponse {
+    recovery_codes: Vec<String>,
+}
+
+/// A struct for requests with a code from an authenticator app
+#[derive(Deserialize)]
+pub struct TwoFactorCodeRequest {
+    code: String,
+}
+
+impl Validate for TwoFactorCodeRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            !self.code.trim().is_empty(),
+            "code",
+            "Please enter the code from your authenticator app",
+        );
+    }
+}
+
+/// A struct for requests to turn off two-factor authentication
+#[derive(Deserialize)]
+pub struct DisableTwoFactorRequest {
+    password: String,
+}
+
+impl Validate for DisableTwoFactorRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            !self.password.is_empty(),
+            "password",
+            "Please enter your password",
+        );
+    }
+}
+
+/// An API endpoint used to start setting up two-factor authentication. It returns a new secret
+/// for the authenticator app, which is only used once the user has confirmed a code from it
+#[post("/api/start_two_factor")]
+pub async fn start_two_factor(
+    AuthenticatedUser(user): AuthenticatedUser,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    // Query the database on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let log_error = |action: &str, err: diesel::result::Error| {
+                log::error!("two_factor.start_two_factor.{}: {}", action, err);
+                EndpointError::InternalError
+            };
+
+            // Replacing an enabled secret would stop the app the user has from working
+            if TwoFactorSecret::is_enabled_for_user(connection, user.id)
+                .map_err(|err| log_error("get", err))?
+            {
+                return Err(EndpointError::Conflict(
+                    "Two-factor authentication is already turned on.",
+                ));
+            }
+
+            let secret = UnsavedTwoFactorSecret::new(&user)
+                .save(connection)
+                .map_err(|err| log_error("save", err))?;
+
+            Ok(Json(TwoFactorSetupResponse {
+                secret: secret.secret_base32(),
+                otpauth_uri: secret.otpauth_uri(&user.username),
+            }))
+        })
+        .await
+}
+
+/// An API endpoint used to finish setting up two-factor authentication with a code from the
+/// authenticator app. It returns the recovery codes, which are not shown again
+#[post("/api/enable_two_factor")]
+pub async fn enable_two_factor(
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<TwoFactorCodeRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let TwoFactorCodeRequest { code } = req_body.0;
+
+    // Hashing the recovery codes is slow, so it runs on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            let log_error = |action: &str, err: diesel::result::Error| {
+                log::error!("two_factor.enable_two_factor.{}: {}", action, err);
+                EndpointError::InternalError
+            };
+
+            let secret = match TwoFactorSecret::get_for_user(connection, user.id)
+                .map_err(|err| log_error("get", err))?
+            {
+                Some(secret) if !secret.is_enabled => secret,
+                Some(_) => {
+                    return Err(EndpointError::Conflict(
+                        "Two-factor authentication is already turned on.",
+                    ))
+                }
+                None => {
+                    return Err(EndpointError::BadClientData(
+                        "Please start setting up two-factor authentication first.",
+                    ))
+                }
+            };
+
+            if !secret
+                .check_code(connection, &code)
+                .map_err(|err| log_error("check", err))?
+            {
+                return Err(EndpointError::BadClientData(
+                    "This code is incorrect. Please check the time on your phone and try again.",
+                ));
+            }
+
+            let recovery_codes = RecoveryCode::generate();
+            let recovery_code_hashes = recovery_codes
+                .iter()
+                .map(|code| UnsavedUser::hash(&RecoveryCode::normalise(code)))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| {
+                    // Log the error
+                    log::error!("two_factor.enable_two_factor.hash: {}", err);
+                    EndpointError::InternalError
+                })?;
+
+            secret
+                .enable(connection, recovery_code_hashes)
+                .map_err(|err| log_error("save", err))?;
+
+            Ok(Json(RecoveryCodesResponse { recovery_codes }))
+        })
+        .await
+}
+
+/// An API endpoint used to turn off two-factor authentication, after confirming the password
+#[post("/api/disable_two_factor")]
+pub async fn disable_two_factor(
+    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<DisableTwoFactorRequest>,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let DisableTwoFactorRequest { password } = req_body.0;
+    let ip = ClientIp::of(&req);
+
+    // Checking the password is slow, so it runs on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            // Make sure that it is the user who is turning it off
+            if !check_password_throttled(
+                connection,
+                &ip,
+                &user,
+                &password,
+                "two_factor.disable_two_factor",
+            )? {
+                return Err(EndpointError::NotAllowed(
+                    "Your password is incorrect. Please try again.",
+                ));
+            }
+
+            TwoFactorSecret::delete_for_user(connection, user.id).map_err(|err| {
+                // Log the error
+                log::error!("two_factor.disable_two_factor.delete: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// An API endpoint used to finish logging in with a code from the authenticator app or a recovery
+/// code, after the password was accepted by `/api/login`.
+/// Wrong codes count as failed logins, so guessing them is throttled like guessing passwords
+#[post("/api/login_two_factor")]
+pub async fn login_two_factor(
+    req: HttpRequest,
+    req_body: Json<TwoFactorCodeRequest>,
+    session: Session,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let TwoFactorCodeRequest { code } = req_body.0;
+    let user_id = get_pending_two_factor(&session).ok_or(EndpointError::BadClientData(
+        "Your login has expired. Please enter your username and password again.",
+    ))?;
+    let session_lifetime = server_state.config.session.lifetime;
+    let ip = ClientIp::of(&req);
+
+    // Checking recovery codes is slow, so it runs on the thread pool for blocking operations
+    let (user, user_session) = server_state
+        .with_connection(move |connection| {
+            let log_error = |action: &str, err: diesel::result::Error| {
+                log::error!("two_factor.login_two_factor.{}: {}", action, err);
+                EndpointError::InternalError
+            };
+
+            let user = User::fetch_by_id(connection, user_id)
+                .map_err(|err| log_error("get_user", err))?
+                .ok_or(EndpointError::NotLoggedIn)?;
+
+            let retry_after = LoginThrottle::retry_after(connection, &ip, &user.username)
+                .map_err(|err| log_error("throttle", err))?;
+            if let Some(retry_after) = retry_after {
+                log::warn!(
+                    "two_factor.login_two_factor.throttled: {} from {}",
+                    user.username,
+                    ip
+                );
+                return Err(EndpointError::TooManyRequests(retry_after));
+            }
+
+            // Two-factor authentication may have been turned off since the password was entered
+            let secret = TwoFactorSecret::get_for_user(connection, user.id)
+                .map_err(|err| log_error("get_secret", err))?
+                .filter(|secret| secret.is_enabled)
+                .ok_or(EndpointError::BadClientData(
+                    "Your login has expired. Please enter your username and password again.",
+                ))?;
+
+            let accepted = if secret
+                .check_code(connection, &code)
+                .map_err(|err| log_error("check_code", err))?
+            {
+                true
+            // Only check the recovery codes if it was not a code from the app, as it is slow
+            } else if RecoveryCode::use_code(connection, user.id, &code)
+                .map_err(|err| log_error("check_recovery_code", err))?
+            {
+                let remaining = RecoveryCode::count_for_user(connection, user.id)
+                    .map_err(|err| log_error("count_recovery_codes", err))?;
+                log::info!(
+                    "two_factor.login_two_factor.recovery_code: {} used a recovery code, {} left",
+                    user.username,
+                    remaining
+                );
+                true
+            } else {
+                false
+            };
+
+            if !accepted {
+                record_login_failure(connection, &ip, &user.username)
+                    .map_err(|err| log_error("record_failure", err))?;
+                return Err(EndpointError::BadClientData(
+                    "This code is incorrect. Please try again.",
+                ));
+            }
+
+            let user_session = start_session(connection, &user, session_lifetime)
+                .map_err(|err| log_error("save_session", err))?;
+
+            Ok((user, user_session))
+        })
+        .await?;
+
+    // This replaces the pending login in the cookie
+    set_session(&session, &user_session).expect("Could not serialise the session");
+    Ok(Json(user.to_public()))
+}
@@ -1,17 +1,29 @@
 use crate::{
     data::{
+        api_tokens::ApiToken,
         group::{ParticipationType, UnsavedGroup, UnsavedGroupParticipant},
+        login_throttle::LoginThrottle,
         models::UnsavedModel,
-        session::{get_session, set_session},
+        session::{
+            get_session, get_session_token, set_pending_two_factor, set_session, AuthenticatedUser,
+            UnsavedUserSession, UserSession,
+        },
+        two_factor::TwoFactorSecret,
         users::User,
     },
-    endpoints::EndpointError,
+    endpoints::{
+        validation::{Validate, ValidationErrors},
+        EndpointError,
+    },
+    password_policy::check_password,
+    proxy::ClientIp,
+    settings::{MAX_USERNAME_LENGTH, TWO_FACTOR_LOGIN_TIMEOUT},
     ServerState,
 };
 use actix_session::Session;
-use actix_web::{get, post, web::Json, Responder};
-use diesel::{result::Error, Connection};
-use serde::Deserialize;
+use actix_web::{get, post, web::Json, Either, HttpRequest, HttpResponse, Responder};
+use diesel::{result::Error, Connection, PgConnection, QueryResult};
+use serde::{Deserialize, Serialize};
 
 use crate::data::users::UnsavedUser;
 
@@ -22,6 +34,13 @@ pub struct SignupRequest {
     password: String,
 }
 
+impl Validate for SignupRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check_text("username", "username", &self.username, MAX_USERNAME_LENGTH);
+        check_password(errors, "password", &self.password, &self.username);
+    }
+}
+
 /// A struct for login requests
 #[derive(Deserialize)]
 pub struct LoginRequest {
@@ -29,110 +48,445 @@ pub struct LoginRequest {
     password: String,
 }
 
+impl Validate for LoginRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            !self.username.is_empty(),
+            "username",
+            "Please enter your username",
+        );
+        // Longer usernames can not exist
+        errors.check(
+            self.username.chars().count() <= MAX_USERNAME_LENGTH,
+            "username",
+            "Incorrect username or password. Please double-check the username and password.",
+        );
+        errors.check(
+            !self.password.is_empty(),
+            "password",
+            "Please enter your password",
+        );
+    }
+}
+
+/// A struct used for responses to logins that still need a two-factor code
+#[derive(Serialize)]
+pub struct TwoFactorRequiredResponse {
+    two_factor_required: bool,
+}
+
+/// A struct for requests to change the password
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    old_password: String,
+    new_password: String,
+}
+
+impl Validate for ChangePasswordRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            !self.old_password.is_empty(),
+            "old_password",
+            "Please enter your current password",
+        );
+        // The new password is checked against the policy once the user is known
+        errors.check(
+            !self.new_password.is_empty(),
+            "new_password",
+            "The new password can not be empty",
+        );
+    }
+}
+
+/// A struct for requests to delete the account
+#[derive(Deserialize)]
+pub struct DeleteAccountRequest {
+    password: String,
+}
+
+impl Validate for DeleteAccountRequest {
+    fn check_fields(&self, errors: &mut ValidationErrors) {
+        errors.check(
+            !self.password.is_empty(),
+            "password",
+            "Please enter your password",
+        );
+    }
+}
+
 /// An API endpoint used to register a user
 #[post("/api/signup")]
 pub async fn signup(
     req_body: Json<SignupRequest>,
     session: Session,
     server_state: actix_web::web::Data<ServerState>,
-) -> Result<&'static str, EndpointError> {
-    // Create a user with those details
-    let unsaved_user = UnsavedUser::try_new(req_body.username.clone(), req_body.password.clone())
-        .expect("Failed to create a user");
-
-    // Get the connection from the mutex
-    let mut connection = server_state
-        .connection
-        .lock()
-        .expect("Could not get the connection from ServerState");
-
-    // Try to save the user
-    let user = connection.transaction::<User, _, _>(|connection| {
-        // save the actual user
-        let user = unsaved_user.save(connection)?;
-        // Create a group just for the user
-        let unsaved_group = UnsavedGroup {
-            name: String::from("Myself"),
-            owner_id: user.id,
-            is_special: true,
-        };
-
-        let group = unsaved_group.save(connection)?;
-        let participation = UnsavedGroupParticipant {
-            participation_type: ParticipationType::Accepted,
-            group_id: group.id,
-            participant_id: user.id,
-        };
-
-        participation.save(connection)?;
-
-        Ok(user)
-    });
-
-    // See if it worked
-    match user {
-        Result::Err(err) => {
-            // Check if the error is due a username uniqueness constraint violation
-            if let Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) = err
-            {
-                log::warn!("users.signup.save.taken: {}", err);
-                // Tell the client to use a unique username
-                Result::Err(EndpointError::BadClientData(
-                    "This username is taken. Try using a different username.",
-                ))
-            } else {
-                // Generic error
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let SignupRequest { username, password } = req_body.0;
+    let session_lifetime = server_state.config.session.lifetime;
 
+    // Hash the password and save the user on the thread pool for blocking operations
+    let (user, user_session) = server_state
+        .with_connection(move |connection| {
+            // Create a user with those details
+            let unsaved_user = UnsavedUser::try_new(username, password).map_err(|err| {
                 // Log the error
-                log::error!("users.signup.save: {}", err);
-                Result::Err(EndpointError::InternalError)
-            }
-        }
-        Result::Ok(user) => {
-            set_session(session, &user).expect("Could not serialise user");
-            Result::Ok("Success!")
-        }
-    }
+                log::error!("users.signup.hash: {}", err);
+                EndpointError::InternalError
+            })?;
+
+            // Try to save the user
+            let user = connection.transaction::<_, Error, _>(|connection| {
+                // save the actual user
+                let user = unsaved_user.save(connection)?;
+                // Create a group just for the user
+                let unsaved_group = UnsavedGroup {
+                    name: String::from("Myself"),
+                    owner_id: user.id,
+                    is_special: true,
+                };
+
+                let group = unsaved_group.save(connection)?;
+                let participation = UnsavedGroupParticipant {
+                    participation_type: ParticipationType::Accepted,
+                    group_id: group.id,
+                    participant_id: user.id,
+                };
+
+                participation.save(connection)?;
+
+                // Log the user in
+                let user_session =
+                    UnsavedUserSession::new(&user, session_lifetime).save(connection)?;
+
+                Ok((user, user_session))
+            });
+
+            // See if it worked
+            user.map_err(|err| {
+                // Check if the error is due a username uniqueness constraint violation
+                if let Error::DatabaseError(diesel::result::DatabaseErrorKind::UniqueViolation, _) =
+                    err
+                {
+                    log::warn!("users.signup.save.taken: {}", err);
+                    // Tell the client to use a unique username
+                    EndpointError::Conflict(
+                        "This username is taken. Try using a different username.",
+                    )
+                } else {
+                    // Generic error
+
+                    // Log the error
+                    log::error!("users.signup.save: {}", err);
+                    EndpointError::InternalError
+                }
+            })
+        })
+        .await?;
+
+    set_session(&session, &user_session).expect("Could not serialise the session");
+    Result::Ok(Json(user.to_public()))
 }
 
-/// An API endpoint used to log in a user
+/// An API endpoint used to log in a user.
+/// Clients that fail to log in too often, from the same IP address or for the same username, have
+/// to wait before trying again, and the account is locked for a while after too many failures.
+/// Users with two-factor authentication are not logged in yet, but have to send a code to
+/// `/api/login_two_factor` next
 #[post("/api/login")]
 pub async fn login(
+    req: HttpRequest,
     req_body: Json<LoginRequest>,
     session: Session,
     server_state: actix_web::web::Data<ServerState>,
-) -> Result<&'static str, EndpointError> {
-    // Get the connection from the mutex
-    let mut connection = server_state
-        .connection
-        .lock()
-        .expect("Could not get the connection from ServerState");
-
-    // Try to get the user from the database with this username and password
-    let user = User::fetch_check(
-        &mut connection,
-        req_body.username.clone(),
-        req_body.password.clone(),
-    );
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let LoginRequest { username, password } = req_body.0;
+    let session_lifetime = server_state.config.session.lifetime;
+    let ip = ClientIp::of(&req);
 
-    // See if it worked
-    match user {
-        Option::None => {
-            // Tell the client that this user does not exist
-            Result::Err(EndpointError::BadClientData(
-                "Incorrect username or password. Please double-check the username and password.",
-            ))
+    // Try to get the user from the database with this username and password.
+    // Checking the password is slow, so it runs on the thread pool for blocking operations
+    let (user, user_session) = server_state
+        .with_connection(move |connection| {
+            let log_error = |action: &str, err: diesel::result::Error| {
+                log::error!("users.login.{}: {}", action, err);
+                EndpointError::InternalError
+            };
+
+            // Do not even check the password if the client has to wait
+            let retry_after = LoginThrottle::retry_after(connection, &ip, &username)
+                .map_err(|err| log_error("throttle", err))?;
+            if let Some(retry_after) = retry_after {
+                log::warn!("users.login.throttled: {} from {}", username, ip);
+                return Err(EndpointError::TooManyRequests(retry_after));
+            }
+
+            let user = match User::fetch_check(connection, username.clone(), password) {
+                Some(user) => user,
+                None => {
+                    record_login_failure(connection, &ip, &username)
+                        .map_err(|err| log_error("record_failure", err))?;
+
+                    // Tell the client that this user does not exist
+                    return Err(EndpointError::BadClientData(
+                        "Incorrect username or password. Please double-check the username and password.",
+                    ));
+                }
+            };
+
+            // The failed logins of users with two-factor authentication are only forgotten once
+            // they have entered a code, so that knowing the password does not allow guessing
+            // codes for longer
+            let two_factor = TwoFactorSecret::is_enabled_for_user(connection, user.id)
+                .map_err(|err| log_error("two_factor", err))?;
+            if two_factor {
+                return Ok((user, None));
+            }
+
+            // Start a new session for the user, clearing out the ones that have expired
+            let user_session = start_session(connection, &user, session_lifetime)
+                .map_err(|err| log_error("save_session", err))?;
+
+            Ok((user, Some(user_session)))
+        })
+        .await?;
+
+    match user_session {
+        Some(user_session) => {
+            set_session(&session, &user_session).expect("Could not serialise the session");
+            Ok(Either::Left(Json(user.to_public())))
         }
-        Option::Some(user) => {
-            set_session(session, &user).expect("Could not serialise user");
-            Result::Ok("Success!")
+        None => {
+            set_pending_two_factor(&session, &user, TWO_FACTOR_LOGIN_TIMEOUT)
+                .expect("Could not serialise the session");
+            Ok(Either::Right(Json(TwoFactorRequiredResponse {
+                two_factor_required: true,
+            })))
         }
     }
 }
 
+/// Count a failed login, logging it if the account gets locked because of it
+pub fn record_login_failure(
+    connection: &mut PgConnection,
+    ip: &str,
+    username: &str,
+) -> QueryResult<()> {
+    if let Some(lockout) = LoginThrottle::record_failure(connection, ip, username)? {
+        log::warn!(
+            "users.login.lockout: locked {} until {} after {} failed logins, the last from {}",
+            lockout.username,
+            lockout.locked_until,
+            lockout.failures,
+            lockout.ip
+        );
+    }
+
+    Ok(())
+}
+
+/// Check the password of a user who is already logged in before something that needs it, e.g.
+/// changing it. It is throttled like logging in, so that someone who got hold of a session can not
+/// guess the password quickly. `endpoint` is used for the logs, e.g. "users.change_password"
+pub fn check_password_throttled(
+    connection: &mut PgConnection,
+    ip: &str,
+    user: &User,
+    password: &str,
+    endpoint: &str,
+) -> Result<bool, EndpointError> {
+    let log_error = |action: &str, err: diesel::result::Error| {
+        log::error!("{}.{}: {}", endpoint, action, err);
+        EndpointError::InternalError
+    };
+
+    // Do not even check the password if the client has to wait
+    let retry_after = LoginThrottle::retry_after(connection, ip, &user.username)
+        .map_err(|err| log_error("throttle", err))?;
+    if let Some(retry_after) = retry_after {
+        log::warn!("{}.throttled: {} from {}", endpoint, user.username, ip);
+        return Err(EndpointError::TooManyRequests(retry_after));
+    }
+
+    if user.check_password(password) {
+        return Ok(true);
+    }
+
+    record_login_failure(connection, ip, &user.username)
+        .map_err(|err| log_error("record_failure", err))?;
+    Ok(false)
+}
+
+/// Start a new session for a user who has logged in, forgetting their failed logins and clearing
+/// out the sessions that have expired
+pub fn start_session(
+    connection: &mut PgConnection,
+    user: &User,
+    session_lifetime: i64,
+) -> QueryResult<UserSession> {
+    LoginThrottle::record_success(connection, &user.username)?;
+    UserSession::delete_expired(connection)?;
+    UnsavedUserSession::new(user, session_lifetime).save(connection)
+}
+
 /// An API endpoint used to check if the user is logged in
 #[get("/api/is_logged_in")]
-pub async fn is_logged_in(session: Session) -> impl Responder {
-    let user = get_session(session);
-    Json(user.is_some())
+pub async fn is_logged_in(
+    session: Session,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    let user = get_session(&session, &server_state).await?;
+    Ok(Json(user.is_some()))
+}
+
+/// An API endpoint used to log out of the current session
+#[post("/api/logout")]
+pub async fn logout(
+    session: Session,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    if let Some(token) = get_session_token(&session) {
+        // End the session on the thread pool for blocking operations
+        server_state
+            .with_connection(move |connection| {
+                UserSession::delete_by_token(connection, &token).map_err(|err| {
+                    // Log the error
+                    log::error!("users.logout.delete: {}", err);
+                    EndpointError::InternalError
+                })
+            })
+            .await?;
+    }
+
+    // Forget the cookie, even if the session had already ended
+    session.purge();
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// An API endpoint used to log out of all the sessions of the user, e.g. on a lost device
+#[post("/api/logout_all_devices")]
+pub async fn logout_all_devices(
+    AuthenticatedUser(user): AuthenticatedUser,
+    session: Session,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    // End the sessions on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            UserSession::delete_all_for_user(connection, user.id).map_err(|err| {
+                // Log the error
+                log::error!("users.logout_all_devices.delete: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?;
+
+    session.purge();
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// An API endpoint used to change the password of the user.
+/// The user stays logged in on this device, but is logged out everywhere else and their API tokens
+/// are revoked
+#[post("/api/change_password")]
+pub async fn change_password(
+    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<ChangePasswordRequest>,
+    session: Session,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let ChangePasswordRequest {
+        old_password,
+        new_password,
+    } = req_body.0;
+    let current_token = get_session_token(&session).ok_or(EndpointError::NotLoggedIn)?;
+
+    let mut errors = ValidationErrors::default();
+    check_password(&mut errors, "new_password", &new_password, &user.username);
+    errors.check(
+        new_password != old_password,
+        "new_password",
+        "The new password has to be different from the current one",
+    );
+    errors.into_result()?;
+    let ip = ClientIp::of(&req);
+
+    // Checking and hashing the passwords is slow, so it runs on the thread pool for blocking
+    // operations
+    server_state
+        .with_connection(move |connection| {
+            if !check_password_throttled(
+                connection,
+                &ip,
+                &user,
+                &old_password,
+                "users.change_password",
+            )? {
+                return Err(EndpointError::NotAllowed(
+                    "Your current password is incorrect. Please try again.",
+                ));
+            }
+
+            let new_password_hash = UnsavedUser::hash(&new_password).map_err(|err| {
+                // Log the error
+                log::error!("users.change_password.hash: {}", err);
+                EndpointError::InternalError
+            })?;
+
+            // Change the password and end the other sessions and the API tokens together, so that
+            // whoever knew the old password can not keep using the account
+            connection
+                .transaction(|connection| {
+                    user.set_password_hash(connection, &new_password_hash)?;
+                    UserSession::delete_others_for_user(connection, user.id, &current_token)?;
+                    ApiToken::delete_all_for_user(connection, user.id)
+                })
+                .map_err(|err| {
+                    // Log the error
+                    log::error!("users.change_password.save: {}", err);
+                    EndpointError::InternalError
+                })
+        })
+        .await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// An API endpoint used to delete the account of the user with everything that belongs to it
+#[post("/api/delete_account")]
+pub async fn delete_account(
+    req: HttpRequest,
+    AuthenticatedUser(user): AuthenticatedUser,
+    req_body: Json<DeleteAccountRequest>,
+    session: Session,
+    server_state: actix_web::web::Data<ServerState>,
+) -> Result<impl Responder, EndpointError> {
+    req_body.validate()?;
+    let DeleteAccountRequest { password } = req_body.0;
+    let ip = ClientIp::of(&req);
+
+    // Checking the password is slow, so it runs on the thread pool for blocking operations
+    server_state
+        .with_connection(move |connection| {
+            // Make sure that it is the user who is deleting the account
+            if !check_password_throttled(connection, &ip, &user, &password, "users.delete_account")?
+            {
+                return Err(EndpointError::NotAllowed(
+                    "Your password is incorrect. Please try again.",
+                ));
+            }
+
+            user.delete(connection).map_err(|err| {
+                // Log the error
+                log::error!("users.delete_account.delete: {}", err);
+                EndpointError::InternalError
+            })
+        })
+        .await?;
+
+    session.purge();
+    Ok(HttpResponse::NoContent().finish())
 }
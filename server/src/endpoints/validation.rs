@@ -0,0 +1,79 @@
+use std::fmt;
+
+use serde::Serialize;
+
+use super::EndpointError;
+
+/// An error in a single field of a request
+#[derive(Serialize, Debug)]
+pub struct FieldError {
+    /// The name of the field, as it is sent by the client
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// The errors found in the fields of a request
+#[derive(Default, Debug)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    /// Record an error in `field`
+    pub fn add(&mut self, field: &'static str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field,
+            message: message.into(),
+        });
+    }
+
+    /// Record an error in `field` unless `is_valid` is true
+    pub fn check(&mut self, is_valid: bool, field: &'static str, message: &str) {
+        if !is_valid {
+            self.add(field, message);
+        }
+    }
+
+    /// Check that a text field is not blank and is at most `max_length` characters long.
+    /// `label` is how the field is called in the error messages
+    pub fn check_text(&mut self, field: &'static str, label: &str, value: &str, max_length: usize) {
+        if value.trim().is_empty() {
+            self.add(field, format!("The {} can not be empty", label));
+        } else if value.chars().count() > max_length {
+            self.add(
+                field,
+                format!(
+                    "The {} can not be longer than {} characters",
+                    label, max_length
+                ),
+            );
+        }
+    }
+
+    /// Fail with the recorded errors, if there are any
+    pub fn into_result(self) -> Result<(), EndpointError> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(EndpointError::InvalidFields(self))
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<_> = self.0.iter().map(|error| error.message.as_str()).collect();
+        write!(f, "{}.", messages.join(". "))
+    }
+}
+
+/// A trait for requests whose fields have to be checked before they are used
+pub trait Validate {
+    /// Record an error for each field of the request that is invalid
+    fn check_fields(&self, errors: &mut ValidationErrors);
+
+    /// Check the fields of the request, failing with all the errors that were found
+    fn validate(&self) -> Result<(), EndpointError> {
+        let mut errors = ValidationErrors::default();
+        self.check_fields(&mut errors);
+        errors.into_result()
+    }
+}
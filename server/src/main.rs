@@ -1,34 +1,90 @@
 use crate::{
+    config::Config,
+    csrf::{check_csrf_token, CSRF_HEADER},
     data::users::UnsavedUser,
-    db::establish_connection,
+    db::{create_pool, DbPool},
+    endpoints::{calendar_feeds::logged_request_line, EndpointError},
     page_template::{create_page, create_session_protected_page},
-    settings::{DOMAIN, PASSWORD_HASH_LENGTH, PORT},
+    proxy::{remove_untrusted_forwarded_headers, ClientIp},
+    security_headers::{add_security_headers, CspNonce},
+    settings::{CORS_MAX_AGE, MAX_CALENDAR_IMPORT_SIZE, PASSWORD_HASH_LENGTH},
+    tls::CertificateStore,
+};
+use actix_cors::Cors;
+use actix_files::Files;
+use actix_session::{config::PersistentSession, storage::CookieSessionStore, SessionMiddleware};
+use actix_web::{
+    cookie::{time::Duration, Key},
+    dev::Service,
+    http::header,
+    middleware, web, App, HttpResponse, HttpServer,
 };
-use actix_session::{storage::CookieSessionStore, SessionMiddleware};
-use actix_web::{cookie::Key, middleware, App, HttpServer};
 use diesel::PgConnection;
 use dotenvy::dotenv;
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use log::LevelFilter;
 use page_template::ReactElement;
-use std::{env, fs::File};
-use std::{io::Read, sync::Mutex};
+use std::{process, str::FromStr, sync::Arc, time};
 
+pub mod config;
+pub mod csrf;
 pub mod data;
 pub mod db;
 pub mod endpoints;
 pub mod page_template;
+pub mod password_policy;
+pub mod proxy;
 pub mod schema;
+pub mod security_headers;
 pub mod settings;
+pub mod tls;
 
 pub struct ServerState {
-    pub connection: Mutex<PgConnection>,
+    pub pool: DbPool,
+    pub config: Arc<Config>,
+}
+
+impl ServerState {
+    /// Run `work` with a connection from the pool on the thread pool for blocking operations.
+    /// Database queries and password hashing block the thread they run on, so running them there
+    /// keeps the worker free to handle other requests
+    pub async fn with_connection<T, F>(&self, work: F) -> Result<T, EndpointError>
+    where
+        F: FnOnce(&mut PgConnection) -> Result<T, EndpointError> + Send + 'static,
+        T: Send + 'static,
+    {
+        let pool = self.pool.clone();
+
+        web::block(move || {
+            // Wait for a connection to become free
+            let mut connection = pool.get().map_err(|err| {
+                // Log the error
+                log::error!("db.get_connection: {}", err);
+                EndpointError::Unavailable
+            })?;
+            work(&mut connection)
+        })
+        .await
+        .map_err(|err| {
+            // The work panicked
+            log::error!("db.with_connection: {}", err);
+            EndpointError::InternalError
+        })?
+    }
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Startup
-    // Create a logger
-    env_logger::init_from_env(env_logger::Env::new().default_filter_or("info"));
+    // Load the .env file, if there is one
+    dotenv().ok();
+
+    // Load the configuration, stopping with a clear message if it is wrong
+    let config = Config::load().unwrap_or_else(|err| exit_with_error("Invalid configuration", err));
+
+    // Create a logger. The level has already been checked
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::from_str(&config.log.level).unwrap_or(LevelFilter::Info))
+        .init();
 
     // Find out the length of the hash in this representation
     let password_length = UnsavedUser::hash("")
@@ -36,60 +92,158 @@ async fn main() -> std::io::Result<()> {
         .len();
     assert_eq!(password_length, PASSWORD_HASH_LENGTH);
 
-    // Load the .env file
-    dotenv().expect("Failed to load the .env file");
+    // load TLS keys, unless a reverse proxy handles TLS.
+    // The certificate is reloaded when it changes, so it can be rotated without a restart
+    let ssl_builder = config.tls.enabled.then(|| {
+        let certificates = CertificateStore::load(&config.tls)
+            .map(Arc::new)
+            .unwrap_or_else(|err| exit_with_error("Could not load the TLS certificate", err));
+        let check_interval = (config.tls.reload_interval > 0)
+            .then(|| time::Duration::from_secs(config.tls.reload_interval));
+        certificates.watch(check_interval);
 
-    // load TLS keys
-    let mut ssl_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
-        .expect("Could not create an SSL builder");
-    ssl_builder
-        .set_private_key_file("ssl/key.pem", SslFiletype::PEM)
-        .expect("Could not locate the key.pem file");
-    ssl_builder
-        .set_certificate_chain_file("ssl/cert.pem")
-        .expect("Could not locate the cert.pem file");
+        certificates
+            .ssl_builder()
+            .unwrap_or_else(|err| exit_with_error("Could not load the TLS certificate", err))
+    });
+    if !config.tls.enabled && config.server.trusted_proxies.is_empty() {
+        log::warn!("TLS is disabled, but no trusted proxies are configured");
+    }
 
-    // create the server
-    let allowed_origin = format!("{DOMAIN}:{PORT}");
-    let server = HttpServer::new(|| {
-        // Connect to the database using the URL in the .env file
-        let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-        let connection = establish_connection(database_url);
-
-        // Set up sessions
-
-        // Read the key file
-        let mut f = File::open("session_key.txt").expect("Could not open the session key file");
-        let mut raw_key = vec![];
-        f.read_to_end(&mut raw_key)
-            .expect("Could not read the session key");
+    // Read the key the session cookies are signed with
+    let session_secret_key = config
+        .session_key()
+        .unwrap_or_else(|err| exit_with_error("Invalid configuration", err));
 
-        let session_secret_key = Key::from(raw_key.as_slice());
+    // Connect to the database. The connections are shared by all the workers
+    let pool = create_pool(&config.database)
+        .unwrap_or_else(|err| exit_with_error("Could not connect to the database", err));
 
+    // create the server
+    let config = Arc::new(config);
+    let bind_address = (config.server.bind_address.clone(), config.server.port);
+    let app_config = config.clone();
+    let server = HttpServer::new(move || {
         // Create server state
         let server_data = actix_web::web::Data::new(ServerState {
-            connection: Mutex::new(connection),
+            pool: pool.clone(),
+            config: app_config.clone(),
         });
 
         App::new()
-            // Activate logger middleware
-            .wrap(middleware::Logger::default())
+            // Reject the requests that other sites make with the session cookie of the user.
+            // It needs the session, so it is added before the session middleware
+            .wrap_fn(|req, service| {
+                let response = match check_csrf_token(&req) {
+                    Ok(()) => Ok(service.call(req)),
+                    // Answer straight away, so that the other middleware still adds its headers
+                    Err(err) => Err(req.into_response(HttpResponse::from_error(err))),
+                };
+                async move {
+                    match response {
+                        Ok(response) => Ok(response.await?.map_into_left_body()),
+                        Err(rejection) => Ok(rejection.map_into_right_body()),
+                    }
+                }
+            })
+            // Activate logger middleware, with the address found from the trusted proxies and
+            // without the secret tokens of the calendar feeds
+            .wrap(
+                middleware::Logger::new(
+                    r#"%{client_ip}xi "%{request_line}xi" %s %b "%{Referer}i" "%{User-Agent}i" %T"#,
+                )
+                .custom_request_replace("client_ip", ClientIp::of)
+                .custom_request_replace("request_line", logged_request_line),
+            )
             // Set up sessions
-            .wrap(SessionMiddleware::new(
-                CookieSessionStore::default(),
-                session_secret_key.clone(),
-            ))
+            .wrap(session_middleware(&app_config, session_secret_key.clone()))
+            // Only allow the pages of the site to call the API from a browser
+            .wrap(cors(&app_config))
+            // Add the security headers to every response. It runs after the forwarded headers are
+            // checked, so that it knows whether the client used HTTPS
+            .wrap_fn({
+                let app_config = app_config.clone();
+                move |req, service| {
+                    let nonce = CspNonce::add_to(&req);
+                    let is_https = req.connection_info().scheme() == "https";
+                    let response = service.call(req);
+                    let app_config = app_config.clone();
+
+                    async move {
+                        let mut response = response.await?;
+                        add_security_headers(
+                            response.headers_mut(),
+                            &app_config.headers,
+                            &nonce,
+                            is_https,
+                        );
+                        Ok(response)
+                    }
+                }
+            })
+            // Only believe the forwarded headers from trusted proxies, and find the address of the
+            // client. This has to run before the other middleware, so it is added last
+            .wrap_fn({
+                let app_config = app_config.clone();
+                move |mut req, service| {
+                    remove_untrusted_forwarded_headers(
+                        &mut req,
+                        &app_config.server.trusted_proxies,
+                    );
+                    ClientIp::add_to(&req, &app_config.server.trusted_proxies);
+                    service.call(req)
+                }
+            })
             // Server state
             .app_data(server_data)
+            // Report the requests that can not be read in the same format as other errors
+            .app_data(
+                web::JsonConfig::default().error_handler(endpoints::malformed_request_handler),
+            )
+            .app_data(
+                web::QueryConfig::default().error_handler(endpoints::malformed_request_handler),
+            )
             // endpoints
             .service(endpoints::users::signup)
             .service(endpoints::users::login)
             .service(endpoints::users::is_logged_in)
+            .service(endpoints::users::logout)
+            .service(endpoints::users::logout_all_devices)
+            .service(endpoints::users::change_password)
+            .service(endpoints::users::delete_account)
+            .service(endpoints::two_factor::start_two_factor)
+            .service(endpoints::two_factor::enable_two_factor)
+            .service(endpoints::two_factor::disable_two_factor)
+            .service(endpoints::two_factor::login_two_factor)
+            .service(endpoints::admin::get_account_lockouts)
+            .service(endpoints::api_tokens::get_api_tokens)
+            .service(endpoints::api_tokens::create_api_token)
+            .service(endpoints::api_tokens::revoke_api_token)
             .service(endpoints::notifications::get_notifications)
             .service(endpoints::events::get_events)
             .service(endpoints::events::create_event)
+            .service(endpoints::events::cancel_event_occurrence)
+            .service(endpoints::events::reschedule_event_occurrence)
+            .service(endpoints::events::update_event)
+            .service(endpoints::events::delete_event)
+            .service(
+                web::resource("/api/import_calendar")
+                    .app_data(
+                        web::JsonConfig::default()
+                            .limit(MAX_CALENDAR_IMPORT_SIZE)
+                            .error_handler(endpoints::malformed_request_handler),
+                    )
+                    .route(web::post().to(endpoints::events::import_calendar_events)),
+            )
+            .service(endpoints::calendar_feeds::get_calendar_feed_url)
+            .service(endpoints::calendar_feeds::reset_calendar_feed_url)
+            .service(endpoints::calendar_feeds::get_calendar_feed)
             .service(endpoints::friends::get_friends)
             .service(endpoints::friends::add_friend)
+            .service(endpoints::friends::get_friend_requests)
+            .service(endpoints::friends::accept_friend_request)
+            .service(endpoints::friends::decline_friend_request)
+            .service(endpoints::friends::cancel_friend_request)
             .service(endpoints::groups::get_owned_groups_with_participants)
             .service(endpoints::groups::create_group)
             .service(endpoints::groups::invite_to_group)
@@ -98,9 +252,9 @@ async fn main() -> std::io::Result<()> {
             .service(endpoints::groups::reply_to_group_invitation)
             // Serving files
             // Serve the static css and js files
-            .service(actix_files::Files::new("/css", "public/css").show_files_listing())
-            .service(actix_files::Files::new("/js", "public/js").show_files_listing())
-            .service(actix_files::Files::new("/img", "public/img").show_files_listing())
+            .service(static_files("/css", "public/css", &app_config))
+            .service(static_files("/js", "public/js", &app_config))
+            .service(static_files("/img", "public/img", &app_config))
             // Serve pages by constructing them out of their components
             .service(create_page(
                 "Log in",
@@ -150,10 +304,63 @@ async fn main() -> std::io::Result<()> {
                     ReactElement::COMPONENT("PageContainerBox"),
                 ],
             ))
-    })
-    // set up openssl for use
-    .bind_openssl(allowed_origin.clone(), ssl_builder)?;
-    println!("Listening on {allowed_origin}");
+    });
+    let server = match ssl_builder {
+        // set up openssl for use
+        Some(ssl_builder) => server.bind_openssl(bind_address, ssl_builder)?,
+        // A reverse proxy handles TLS
+        None => server.bind(bind_address)?,
+    };
+    log::info!(
+        "Listening on {}:{}, reachable at {}",
+        config.server.bind_address,
+        config.server.port,
+        config.public_url()
+    );
 
     server.run().await
 }
+
+/// Stop the server before it starts, explaining what went wrong
+fn exit_with_error(context: &str, err: impl std::fmt::Display) -> ! {
+    eprintln!("{context}: {err}");
+    process::exit(1);
+}
+
+/// Serve the static files in `directory` at `path`. The directories are only listed if the
+/// configuration allows it
+fn static_files(path: &str, directory: &str, config: &Config) -> Files {
+    let files = Files::new(path, directory);
+    if config.server.list_directories {
+        files.show_files_listing()
+    } else {
+        files
+    }
+}
+
+/// Build the CORS policy, which only allows requests from the origin the site is served from
+fn cors(config: &Config) -> Cors {
+    Cors::default()
+        .allowed_origin(&config.public_origin())
+        .allowed_methods(["GET", "POST"])
+        .allowed_headers([header::CONTENT_TYPE, header::ACCEPT, header::AUTHORIZATION])
+        .allowed_header(CSRF_HEADER)
+        .supports_credentials()
+        .max_age(CORS_MAX_AGE)
+}
+
+/// Set up the session cookies according to the configuration
+fn session_middleware(config: &Config, key: Key) -> SessionMiddleware<CookieSessionStore> {
+    let mut builder = SessionMiddleware::builder(CookieSessionStore::default(), key)
+        .cookie_name(config.session.cookie_name.clone())
+        .cookie_secure(config.session.cookie_secure)
+        .cookie_same_site(config.session.cookie_same_site.into());
+
+    if let Some(max_age) = config.session.cookie_max_age {
+        builder = builder.session_lifecycle(
+            PersistentSession::default().session_ttl(Duration::seconds(max_age)),
+        );
+    }
+
+    builder.build()
+}
@@ -1,31 +1,77 @@
 use std::fs;
 
 use actix_session::Session;
-use actix_web::{http::header::LOCATION, web, HttpResponse, Resource};
+use actix_web::{
+    http::header::{CACHE_CONTROL, LOCATION},
+    web, HttpRequest, HttpResponse, Resource,
+};
 use askama::Template;
 
-use crate::{data::session::get_session, settings::COMPONENTS_ALWAYS_INCLUDED};
+use crate::{
+    csrf::get_or_create_csrf_token, data::session::get_session, endpoints::EndpointError,
+    security_headers::CspNonce, settings::COMPONENTS_ALWAYS_INCLUDED, ServerState,
+};
 
 /// A struct used to compile a page
 #[derive(Template)]
 #[template(path = "_app.html", escape = "none")]
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub struct PageTemplate<'a> {
     /// What to display in the `title` tag
     title: &'a str,
     /// The name of the component that is used as an entry point
     page_component_name: &'a str,
     /// The pieces of code to add to the page
-    components: Vec<String>,
+    components: &'a [String],
+    /// The token the scripts on the page send with their requests, see `csrf::check_csrf_token`
+    csrf_token: &'a str,
+    /// The nonce that allows the scripts on the page to run, see `security_headers::CspNonce`
+    csp_nonce: &'a str,
 }
 
-/// Generate the text to be sent as a response to a page request
+impl PageTemplate<'static> {
+    /// Render the page for a request, with the CSRF token of its session and its nonce
+    fn respond(self, req: &HttpRequest, session: &Session) -> HttpResponse {
+        let csrf_token = match get_or_create_csrf_token(session) {
+            Ok(csrf_token) => csrf_token,
+            Err(err) => {
+                // Log the error
+                log::error!("page_template.respond.csrf_token: {}", err);
+                return HttpResponse::from_error(EndpointError::InternalError);
+            }
+        };
+
+        let csp_nonce = CspNonce::of(req);
+        let template = PageTemplate {
+            csrf_token: &csrf_token,
+            csp_nonce: &csp_nonce,
+            ..self
+        };
+        match template.render() {
+            Ok(body) => HttpResponse::Ok()
+                .content_type("text/html; charset=utf-8")
+                // The page contains the CSRF token of the session, so it must not be reused
+                .insert_header((CACHE_CONTROL, "no-store"))
+                .body(body),
+            Err(err) => {
+                // Log the error
+                log::error!("page_template.respond.render: {}", err);
+                HttpResponse::from_error(EndpointError::InternalError)
+            }
+        }
+    }
+}
+
+/// Generate the template to be rendered as a response to a page request
 /// `title` is the name of the page to be displayed using the `title` tag
 /// `path` is the URL path under which this resource would be located
 /// `elements` are the react elements to be included in the page
 /// # Panics
 /// This function panics if the react elements do not include exactly one page element or if the files for the elements could not be read.
-fn create_template(title: &'static str, elements: &'static [ReactElement]) -> &'static mut String {
+fn create_template(
+    title: &'static str,
+    elements: &'static [ReactElement],
+) -> PageTemplate<'static> {
     // Find all the page elements
     let page_elements: Vec<_> = elements.iter().filter(|e| e.is_page()).collect();
     // Check that exactly one page element was given
@@ -37,18 +83,20 @@ fn create_template(title: &'static str, elements: &'static [ReactElement]) -> &'
     let common_components = COMPONENTS_ALWAYS_INCLUDED.iter();
     let components = elements.into_iter().chain(common_components);
     // Read all the code for the elements
-    let components = components.map(|e| e.read_code()).collect();
+    let components: Vec<_> = components.map(|e| e.read_code()).collect();
+
+    // leak the code so that it can be referenced later.
+    // This is OK to do because it is only created once and it does need to be referenced for the rest of the program
+    let components = Box::leak(components.into_boxed_slice());
 
-    let template = PageTemplate {
+    PageTemplate {
         title,
         page_component_name,
         components,
-    };
-    let template = template.render().expect("Could not render a template");
-
-    // leak the template so that it can be referenced later.
-    // This is OK to do because it is only created once and it does need to be referenced for the rest of the program
-    Box::leak(Box::new(template))
+        // Each request gets the token of its session and its own nonce
+        csrf_token: "",
+        csp_nonce: "",
+    }
 }
 
 /// Create the code for a page based on the components that need to be included
@@ -63,23 +111,27 @@ pub fn create_session_protected_page(
     path: &str,
     elements: &'static [ReactElement],
 ) -> Resource {
+    // Share the template between the requests
     let template = create_template(title, elements);
 
     // create an actix resource handler
-    web::resource(path).to(|session: Session| async {
-        // Check if the session is valid
-        let user = get_session(session);
-        // If it is not, redirect to login
-        if user.is_none() {
-            return HttpResponse::TemporaryRedirect()
-                .insert_header((LOCATION, "/login"))
-                .finish();
-        }
-        // Otherwise, build a normal response
-        HttpResponse::Ok()
-            .content_type("text/html; charset=utf-8")
-            .body(template.clone())
-    })
+    web::resource(path).to(
+        move |req: HttpRequest, session: Session, server_state: web::Data<ServerState>| async move {
+            // Check if the session is valid
+            let user = match get_session(&session, &server_state).await {
+                Ok(user) => user,
+                Err(err) => return HttpResponse::from_error(err),
+            };
+            // If it is not, redirect to login
+            if user.is_none() {
+                return HttpResponse::TemporaryRedirect()
+                    .insert_header((LOCATION, "/login"))
+                    .finish();
+            }
+            // Otherwise, build a normal response
+            template.respond(&req, &session)
+        },
+    )
 }
 
 /// Create the code for a page based on the components that need to be included
@@ -91,10 +143,8 @@ pub fn create_session_protected_page(
 pub fn create_page(title: &'static str, path: &str, elements: &'static [ReactElement]) -> Resource {
     let template = create_template(title, elements);
     // create an actix resource handler
-    web::resource(path).to(|| async {
-        HttpResponse::Ok()
-            .content_type("text/html; charset=utf-8")
-            .body(template.clone())
+    web::resource(path).to(move |req: HttpRequest, session: Session| async move {
+        template.respond(&req, &session)
     })
 }
 
@@ -0,0 +1,324 @@
+use std::{collections::HashMap, sync::OnceLock};
+
+use crate::{
+    endpoints::validation::ValidationErrors,
+    settings::{MAX_PASSWORD_LENGTH, MIN_PASSWORD_LENGTH, MIN_PASSWORD_SCORE},
+};
+
+/// The most common passwords, most common first
+const COMMON_PASSWORDS: &str = include_str!("../common_passwords.txt");
+
+/// The shortest part of a password that is looked up in the list of common passwords
+const MIN_WORD_LENGTH: usize = 4;
+
+/// Get the position of each common password in the list, starting from 1
+fn common_password_ranks() -> &'static HashMap<&'static str, usize> {
+    static RANKS: OnceLock<HashMap<&'static str, usize>> = OnceLock::new();
+    RANKS.get_or_init(|| {
+        COMMON_PASSWORDS
+            .lines()
+            // Skip the comments
+            .filter(|line| !line.starts_with('#') && !line.is_empty())
+            .enumerate()
+            .map(|(index, password)| (password, index + 1))
+            .collect()
+    })
+}
+
+/// Replace the characters that are commonly used instead of letters, e.g. 4 for a
+fn unleet(character: char) -> char {
+    match character {
+        '4' | '@' => 'a',
+        '8' => 'b',
+        '3' => 'e',
+        '6' | '9' => 'g',
+        '1' | '!' | '|' => 'i',
+        '0' => 'o',
+        '5' | '$' => 's',
+        '7' | '+' => 't',
+        '2' => 'z',
+        _ => character,
+    }
+}
+
+/// The number of characters of the same kind as `character`, which an attacker has to try for
+/// each character that is guessed without a pattern
+fn character_set_size(character: char) -> f64 {
+    if character.is_ascii_digit() {
+        10.0
+    } else if character.is_ascii_alphabetic() {
+        26.0
+    } else if character.is_ascii() {
+        33.0
+    } else {
+        100.0
+    }
+}
+
+/// Find the longest common password that `characters` start with, returning its length and the
+/// number of guesses needed to find it
+fn match_common_password(characters: &[char]) -> Option<(usize, f64)> {
+    let ranks = common_password_ranks();
+
+    (MIN_WORD_LENGTH..=characters.len())
+        .rev()
+        .find_map(|length| {
+            let word = &characters[..length];
+            let lowercase: String = word.iter().flat_map(|c| c.to_lowercase()).collect();
+            let unleeted: String = lowercase.chars().map(unleet).collect();
+
+            let (rank, variations) =
+                match (ranks.get(lowercase.as_str()), ranks.get(unleeted.as_str())) {
+                    (Some(rank), _) => (*rank, 1.0),
+                    // Replacing letters only doubles the guesses, as attackers try it too
+                    (None, Some(rank)) => (*rank, 2.0),
+                    (None, None) => return None,
+                };
+            let capitalised = if word.iter().any(|c| c.is_uppercase()) {
+                2.0
+            } else {
+                1.0
+            };
+
+            Some((length, rank as f64 * variations * capitalised))
+        })
+}
+
+/// Find a run of the same character, or of consecutive ones like abc or 321, at the start of
+/// `characters`, returning its length and the number of guesses needed to find it
+fn match_run(characters: &[char]) -> Option<(usize, f64)> {
+    if characters.len() < 3 {
+        return None;
+    }
+
+    let step = characters[1] as i64 - characters[0] as i64;
+    if step.abs() > 1 {
+        return None;
+    }
+
+    let length = 1 + characters
+        .windows(2)
+        .take_while(|pair| pair[1] as i64 - pair[0] as i64 == step)
+        .count();
+    if length < 3 {
+        return None;
+    }
+
+    // The attacker only has to guess where the run starts and how long it is
+    Some((length, character_set_size(characters[0]) * length as f64))
+}
+
+/// Find a year like 1987 or 2023 at the start of `characters`
+fn match_year(characters: &[char]) -> Option<(usize, f64)> {
+    let year: String = characters.iter().take(4).collect();
+    let is_year = year.len() == 4
+        && (year.starts_with("19") || year.starts_with("20"))
+        && year.chars().all(|c| c.is_ascii_digit());
+
+    is_year.then_some((4, 200.0))
+}
+
+/// Estimate how many guesses an attacker needs to find a password, by splitting it into common
+/// passwords, runs, years and characters that have to be guessed one by one.
+/// It is a simpler version of what zxcvbn does in the browser
+fn estimate_guesses(password: &str) -> f64 {
+    let characters: Vec<_> = password.chars().collect();
+    let mut guesses = 1.0;
+    let mut position = 0;
+
+    while position < characters.len() {
+        let rest = &characters[position..];
+        let (length, part_guesses) = match_common_password(rest)
+            .or_else(|| match_run(rest))
+            .or_else(|| match_year(rest))
+            .unwrap_or_else(|| (1, character_set_size(rest[0])));
+
+        guesses *= part_guesses;
+        position += length;
+    }
+
+    guesses
+}
+
+/// Score how hard a password is to guess from 0 to 4, on the same scale as zxcvbn
+pub fn password_score(password: &str) -> u8 {
+    match estimate_guesses(password) {
+        guesses if guesses < 1e3 => 0,
+        guesses if guesses < 1e6 => 1,
+        guesses if guesses < 1e8 => 2,
+        guesses if guesses < 1e10 => 3,
+        _ => 4,
+    }
+}
+
+/// Check that a password is allowed for the user called `username`, recording an error in `field`
+/// for the first rule it breaks
+pub fn check_password(
+    errors: &mut ValidationErrors,
+    field: &'static str,
+    password: &str,
+    username: &str,
+) {
+    let length = password.chars().count();
+    if length < MIN_PASSWORD_LENGTH {
+        errors.add(
+            field,
+            format!(
+                "The password has to be at least {} characters long",
+                MIN_PASSWORD_LENGTH
+            ),
+        );
+        // The other rules do not matter for a password that is too short
+        return;
+    }
+    if length > MAX_PASSWORD_LENGTH {
+        errors.add(
+            field,
+            format!(
+                "The password can not be longer than {} characters",
+                MAX_PASSWORD_LENGTH
+            ),
+        );
+        return;
+    }
+
+    let lowercase = password.to_lowercase();
+    let lowercase_username = username.trim().to_lowercase();
+    if common_password_ranks().contains_key(lowercase.as_str()) {
+        errors.add(
+            field,
+            "This is one of the most common passwords. Please choose a different one",
+        );
+    } else if lowercase_username.chars().count() >= 3 && lowercase.contains(&lowercase_username) {
+        errors.add(field, "The password can not contain your username");
+    } else if password_score(password) < MIN_PASSWORD_SCORE {
+        errors.add(
+            field,
+            "This password is too easy to guess. Try making it longer or adding uncommon words",
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::endpoints::EndpointError;
+
+    /// Check a password for the user `alice`, returning the error messages
+    fn password_errors(password: &str) -> Vec<String> {
+        let mut errors = ValidationErrors::default();
+        check_password(&mut errors, "password", password, "alice");
+        errors.0.into_iter().map(|error| error.message).collect()
+    }
+
+    #[test]
+    fn scores_passwords_like_zxcvbn() {
+        assert_eq!(password_score(""), 0);
+        assert_eq!(password_score("password"), 0);
+        assert_eq!(password_score("aaaaaaaaaaaa"), 0);
+        assert_eq!(password_score("abcdef123456"), 0);
+        assert_eq!(password_score("Password1987"), 1);
+        assert_eq!(password_score("correct horse battery staple"), 4);
+        assert_eq!(password_score("q8Jz!w2Lp#x"), 4);
+    }
+
+    #[test]
+    fn counts_common_passwords_with_replaced_letters_as_weak() {
+        assert!(password_score("P@ssw0rd") < MIN_PASSWORD_SCORE);
+        assert!(password_score("5unsh1ne") < MIN_PASSWORD_SCORE);
+        assert!(password_score("Sunsh1ne2023") < MIN_PASSWORD_SCORE);
+    }
+
+    #[test]
+    fn accepts_strong_passwords() {
+        for password in [
+            "correct horse battery staple",
+            "Battery-staple-7",
+            "q8Jz!w2Lp#x",
+            // Exactly as long as allowed
+            &"x7#Kq".repeat(MIN_PASSWORD_LENGTH)[..MIN_PASSWORD_LENGTH],
+            &"x7#Kq".repeat(MAX_PASSWORD_LENGTH)[..MAX_PASSWORD_LENGTH],
+        ] {
+            assert_eq!(
+                password_errors(password),
+                Vec::<String>::new(),
+                "{password}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_passwords_of_the_wrong_length() {
+        let too_short = ["The password has to be at least 8 characters long"];
+        assert_eq!(password_errors(""), too_short);
+        assert_eq!(password_errors("q8J!w2L"), too_short);
+        // Characters are counted, not bytes
+        assert_eq!(password_errors("ääääää"), too_short);
+
+        assert_eq!(
+            password_errors(&"q8J!w2Lp".repeat(17)[..MAX_PASSWORD_LENGTH + 1]),
+            ["The password can not be longer than 128 characters"]
+        );
+    }
+
+    #[test]
+    fn rejects_common_passwords_in_any_case() {
+        for password in ["password", "PASSWORD", "sunshine", "12345678"] {
+            assert_eq!(
+                password_errors(password),
+                ["This is one of the most common passwords. Please choose a different one"],
+                "{password}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_passwords_with_the_username() {
+        let contains_username = ["The password can not contain your username"];
+        assert_eq!(password_errors("q8J!alice2Lp"), contains_username);
+        assert_eq!(password_errors("q8J!ALICE2Lp"), contains_username);
+
+        // A password equal to the username, in a different case
+        let mut errors = ValidationErrors::default();
+        check_password(&mut errors, "password", "Alexander", " alexander ");
+        assert_eq!(errors.0[0].message, contains_username[0]);
+
+        // Very short usernames are likely to appear by chance
+        let mut errors = ValidationErrors::default();
+        check_password(&mut errors, "password", "q8J!jo2Lp#x", "jo");
+        assert!(errors.0.is_empty());
+    }
+
+    #[test]
+    fn rejects_passwords_that_are_easy_to_guess() {
+        for password in [
+            "aaaaaaaaaaaa",
+            "abcdef123456",
+            "Password1987",
+            "qwertyuiop12",
+            "dragonmonkey",
+        ] {
+            assert_eq!(
+                password_errors(password),
+                [
+                    "This password is too easy to guess. Try making it longer or adding uncommon words"
+                ],
+                "{password}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_a_single_error_in_the_given_field() {
+        let mut errors = ValidationErrors::default();
+        // Too short, common and easy to guess at once
+        check_password(&mut errors, "new_password", "1234", "alice");
+
+        assert_eq!(errors.0.len(), 1);
+        assert_eq!(errors.0[0].field, "new_password");
+        assert!(matches!(
+            errors.into_result(),
+            Err(EndpointError::InvalidFields(errors)) if errors.0.len() == 1
+        ));
+    }
+}
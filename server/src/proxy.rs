@@ -0,0 +1,141 @@
+use std::net::{IpAddr, SocketAddr};
+
+use actix_web::{dev::ServiceRequest, HttpMessage};
+
+/// The headers a reverse proxy uses to pass on the details of the original request.
+/// actix reads them to find the address and the protocol of the client, e.g. for the logs
+const FORWARDED_HEADERS: [&str; 4] = [
+    "forwarded",
+    "x-forwarded-for",
+    "x-forwarded-proto",
+    "x-forwarded-host",
+];
+
+/// Remove the forwarded headers from a request unless it came from one of the trusted proxies, so
+/// that clients can not pretend to have a different address or protocol
+pub fn remove_untrusted_forwarded_headers(req: &mut ServiceRequest, trusted_proxies: &[IpAddr]) {
+    let is_trusted = req
+        .peer_addr()
+        // Treat IPv4 addresses written as IPv6 the same as the rest
+        .map(|addr| trusted_proxies.contains(&addr.ip().to_canonical()))
+        .unwrap_or(false);
+
+    if !is_trusted {
+        let headers = req.headers_mut();
+        for name in FORWARDED_HEADERS {
+            headers.remove(name);
+        }
+    }
+}
+
+/// The address of the client that sent a request. It is found once by `ClientIp::add_to`, so that
+/// everything that needs it, like the login throttle and the logs, agrees on it
+#[derive(Clone, Copy)]
+pub struct ClientIp(Option<IpAddr>);
+
+impl ClientIp {
+    /// Find the address of the client, so that handlers can get it with `ClientIp::of`.
+    /// It has to run after `remove_untrusted_forwarded_headers`
+    pub fn add_to(req: &ServiceRequest, trusted_proxies: &[IpAddr]) {
+        // A proxy can add a header line of its own instead of adding to the existing one, so all
+        // of them are read in order. A value that is not text can not be used
+        let forwarded_for: Vec<&str> = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .flat_map(|value| value.to_str().unwrap_or("").split(','))
+            .collect();
+
+        let ip = req
+            .peer_addr()
+            .map(|addr| find_client_ip(addr.ip().to_canonical(), &forwarded_for, trusted_proxies));
+        req.extensions_mut().insert(ClientIp(ip));
+    }
+
+    /// Get the address of the client that sent a request
+    pub fn of(req: &impl HttpMessage) -> String {
+        req.extensions()
+            .get::<ClientIp>()
+            .and_then(|ip| ip.0)
+            .map(|ip| ip.to_string())
+            .unwrap_or_else(|| String::from("unknown"))
+    }
+}
+
+/// Find the address of the client from the address of the peer and the `X-Forwarded-For` entries.
+/// Every proxy adds the address it got the request from to the end of the list, and the client can
+/// put anything at the start of it. So the list is read from the end, and only for as long as the
+/// addresses were added by trusted proxies
+fn find_client_ip(peer: IpAddr, forwarded_for: &[&str], trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+
+    for entry in forwarded_for.iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+
+        // Some proxies add the port as well
+        let entry = entry.trim();
+        let ip = entry
+            .parse::<IpAddr>()
+            .or_else(|_| entry.parse::<SocketAddr>().map(|addr| addr.ip()));
+        match ip {
+            Ok(ip) => client = ip.to_canonical(),
+            // A trusted proxy would not add this, so the client is the proxy that sent it on
+            Err(_) => break,
+        }
+    }
+
+    client
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(text: &str) -> IpAddr {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn ignores_the_header_from_untrusted_peers() {
+        let client = find_client_ip(ip("203.0.113.7"), &["198.51.100.1"], &[ip("10.0.0.1")]);
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn uses_the_address_added_by_the_proxy() {
+        let client = find_client_ip(
+            ip("10.0.0.1"),
+            // The client made up the first entry
+            &["198.51.100.1", " 203.0.113.7"],
+            &[ip("10.0.0.1")],
+        );
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn skips_every_trusted_proxy() {
+        let client = find_client_ip(
+            ip("10.0.0.1"),
+            &["198.51.100.1", "203.0.113.7:4321", "10.0.0.2"],
+            &[ip("10.0.0.1"), ip("10.0.0.2")],
+        );
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn stops_at_entries_that_are_not_addresses() {
+        let client = find_client_ip(
+            ip("10.0.0.1"),
+            &["198.51.100.1", "not an address"],
+            &[ip("10.0.0.1")],
+        );
+        assert_eq!(client, ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn treats_ipv4_written_as_ipv6_as_ipv4() {
+        let client = find_client_ip(ip("10.0.0.1"), &["::ffff:203.0.113.7"], &[ip("10.0.0.1")]);
+        assert_eq!(client, ip("203.0.113.7"));
+    }
+}
@@ -1,40 +1,111 @@
 // @generated automatically by Diesel CLI.
 
 pub mod sql_types {
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "friendship_status"))]
+    pub struct FriendshipStatus;
+
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "participation_type"))]
     pub struct ParticipationType;
 
     #[derive(diesel::sql_types::SqlType)]
-    #[diesel(postgres_type(name = "recurrence_type"))]
-    pub struct RecurrenceType;
+    #[diesel(postgres_type(name = "recurrence_frequency"))]
+    pub struct RecurrenceFrequency;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "throttle_kind"))]
+    pub struct ThrottleKind;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "token_scope"))]
+    pub struct TokenScope;
 
     #[derive(diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "visibility_type"))]
     pub struct VisibilityType;
+
+    #[derive(diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "weekday"))]
+    pub struct Weekday;
+}
+
+diesel::table! {
+    account_lockouts (id) {
+        id -> Int4,
+        username -> Varchar,
+        ip -> Varchar,
+        failures -> Int4,
+        locked_at -> Int8,
+        locked_until -> Int8,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::TokenScope;
+
+    api_tokens (id) {
+        id -> Int4,
+        user_id -> Int4,
+        name -> Varchar,
+        token_hash -> Varchar,
+        scopes -> Array<TokenScope>,
+        created_at -> Int8,
+        last_used_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    calendar_feeds (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token -> Varchar,
+    }
+}
+
+diesel::table! {
+    event_exceptions (id) {
+        id -> Int4,
+        event_id -> Int4,
+        original_start_time -> Int8,
+        is_cancelled -> Bool,
+        new_start_time -> Nullable<Int8>,
+        new_duration -> Nullable<Int8>,
+    }
 }
 
 diesel::table! {
     use diesel::sql_types::*;
     use super::sql_types::VisibilityType;
-    use super::sql_types::RecurrenceType;
+    use super::sql_types::RecurrenceFrequency;
+    use super::sql_types::Weekday;
 
     events (id) {
         id -> Int4,
         title -> Varchar,
         visibility -> VisibilityType,
-        start_time -> Int4,
-        duration -> Int4,
-        recurrence -> RecurrenceType,
+        start_time -> Int8,
+        duration -> Int8,
         group_id -> Int4,
+        recurrence_frequency -> RecurrenceFrequency,
+        recurrence_interval -> Int4,
+        recurrence_by_day -> Array<Weekday>,
+        recurrence_until -> Nullable<Int8>,
+        recurrence_count -> Nullable<Int4>,
+        time_zone -> Varchar,
     }
 }
 
 diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::FriendshipStatus;
+
     friendships (id) {
         id -> Int4,
         owner_id -> Int4,
         friend_id -> Int4,
+        status -> FriendshipStatus,
     }
 }
 
@@ -59,6 +130,48 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::ThrottleKind;
+
+    login_throttles (id) {
+        id -> Int4,
+        kind -> ThrottleKind,
+        subject -> Varchar,
+        failures -> Int4,
+        last_failure_at -> Int8,
+        blocked_until -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        id -> Int4,
+        user_id -> Int4,
+        code_hash -> Text,
+    }
+}
+
+diesel::table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token -> Varchar,
+        created_at -> Int8,
+        expires_at -> Int8,
+    }
+}
+
+diesel::table! {
+    two_factor_secrets (id) {
+        id -> Int4,
+        user_id -> Int4,
+        secret -> Bytea,
+        is_enabled -> Bool,
+        last_used_step -> Nullable<Int8>,
+    }
+}
+
 diesel::table! {
     users (id) {
         id -> Int4,
@@ -67,15 +180,29 @@ diesel::table! {
     }
 }
 
+diesel::joinable!(api_tokens -> users (user_id));
+diesel::joinable!(calendar_feeds -> users (user_id));
+diesel::joinable!(event_exceptions -> events (event_id));
 diesel::joinable!(events -> groups (group_id));
 diesel::joinable!(groups -> users (owner_id));
 diesel::joinable!(groups_participants -> groups (group_id));
 diesel::joinable!(groups_participants -> users (participant_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(sessions -> users (user_id));
+diesel::joinable!(two_factor_secrets -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    account_lockouts,
+    api_tokens,
+    calendar_feeds,
+    event_exceptions,
     events,
     friendships,
     groups,
     groups_participants,
+    login_throttles,
+    recovery_codes,
+    sessions,
+    two_factor_secrets,
     users,
 );
@@ -0,0 +1,80 @@
+use actix_web::{
+    dev::ServiceRequest,
+    http::header::{
+        HeaderMap, HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY,
+        STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+    },
+    HttpMessage, HttpRequest,
+};
+
+use crate::{config::HeadersConfig, data::models::random_token};
+
+/// The part of the Content-Security-Policy that is replaced with the nonce of the request
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// A random value that is new for every request. The scripts on a page are marked with it, so that
+/// the Content-Security-Policy only allows those scripts to run
+#[derive(Clone)]
+pub struct CspNonce(String);
+
+impl CspNonce {
+    /// Create a nonce for a request, so that pages can get it with `CspNonce::of`
+    pub fn add_to(req: &ServiceRequest) -> CspNonce {
+        let nonce = CspNonce(random_token());
+        req.extensions_mut().insert(nonce.clone());
+        nonce
+    }
+
+    /// Get the nonce of a request
+    pub fn of(req: &HttpRequest) -> String {
+        req.extensions()
+            .get::<CspNonce>()
+            .map(|nonce| nonce.0.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Add the security headers to a response, unless it already has them.
+/// `is_https` is whether the client used HTTPS, as browsers ignore HSTS over plain HTTP
+pub fn add_security_headers(
+    headers: &mut HeaderMap,
+    config: &HeadersConfig,
+    nonce: &CspNonce,
+    is_https: bool,
+) {
+    let hsts = if is_https && config.hsts_max_age > 0 {
+        format!("max-age={}", config.hsts_max_age)
+    } else {
+        String::new()
+    };
+
+    let values = [
+        (
+            CONTENT_SECURITY_POLICY,
+            config
+                .content_security_policy
+                .replace(NONCE_PLACEHOLDER, &nonce.0),
+        ),
+        (STRICT_TRANSPORT_SECURITY, hsts),
+        (X_FRAME_OPTIONS, config.frame_options.clone()),
+        (REFERRER_POLICY, config.referrer_policy.clone()),
+        // Stop browsers from guessing that a file is e.g. a script
+        (X_CONTENT_TYPE_OPTIONS, String::from("nosniff")),
+    ];
+
+    for (name, value) in values {
+        add_header(headers, name, &value);
+    }
+}
+
+/// Add a header if the response does not have it and the value is not empty
+fn add_header(headers: &mut HeaderMap, name: HeaderName, value: &str) {
+    if value.is_empty() || headers.contains_key(&name) {
+        return;
+    }
+
+    // The values are checked when the configuration is loaded
+    if let Ok(value) = HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
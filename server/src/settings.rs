@@ -2,10 +2,63 @@ use crate::page_template::ReactElement;
 
 pub const PASSWORD_HASH_LENGTH: usize = 96;
 
-pub const PORT: &str = "8080";
-pub const DOMAIN: &str = "localhost";
+/// The longest a username can be, in characters
+pub const MAX_USERNAME_LENGTH: usize = 50;
+/// The longest a group name can be, in characters
+pub const MAX_GROUP_NAME_LENGTH: usize = 100;
+/// The longest an event title can be, in characters
+pub const MAX_EVENT_TITLE_LENGTH: usize = 100;
+/// The longest the name of an API token can be, in characters
+pub const MAX_API_TOKEN_NAME_LENGTH: usize = 100;
+/// The shortest a password can be, in characters
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+/// The longest a password can be, in characters. Hashing very long passwords is slow
+pub const MAX_PASSWORD_LENGTH: usize = 128;
+/// How hard a password has to be to guess, from 0 to 4 like the strength bar of the signup page
+pub const MIN_PASSWORD_SCORE: u8 = 2;
 
-pub const PROTOCOL: &str = "https";
+/// The latest time, as a UNIX timestamp in seconds, that an occurrence of an event can be moved to
+/// or end at, the end of the year 9999
+pub const MAX_EVENT_TIME: i64 = 253_402_300_799;
+/// The largest calendar file, in bytes of JSON, that can be imported at once. Parsing the file
+/// takes a while, so it is smaller than the default limit of JSON requests
+pub const MAX_CALENDAR_IMPORT_SIZE: usize = 1024 * 1024;
+/// The longest time window, in seconds, that events can be requested for at once
+pub const MAX_EVENTS_WINDOW: i64 = 366 * 24 * 60 * 60;
+
+/// The number of failed logins from an IP address before it has to wait between attempts.
+/// It is higher than for a username, as many users can share an address
+pub const LOGIN_FREE_ATTEMPTS_PER_IP: i32 = 10;
+/// The number of failed logins for a username before it has to wait between attempts
+pub const LOGIN_FREE_ATTEMPTS_PER_USERNAME: i32 = 3;
+/// How many seconds to wait after the first failed login that is not free. The wait doubles with
+/// every further failure
+pub const LOGIN_BACKOFF_BASE: i64 = 1;
+/// The longest wait between failed logins, in seconds
+pub const LOGIN_BACKOFF_MAX: i64 = 5 * 60;
+/// The number of failed logins for a username after which the account is locked
+pub const LOGIN_LOCKOUT_FAILURES: i32 = 10;
+/// How many seconds an account stays locked for
+pub const LOGIN_LOCKOUT_DURATION: i64 = 15 * 60;
+/// How many seconds failed logins are remembered for after the last one
+pub const LOGIN_FAILURE_MEMORY: i64 = 60 * 60;
+/// The number of the latest account lockouts that admins are shown
+pub const ACCOUNT_LOCKOUTS_SHOWN: i64 = 100;
+
+/// The name authenticator apps show next to the two-factor codes for this site
+pub const TWO_FACTOR_ISSUER: &str = "Calendar";
+/// How many seconds each two-factor code is valid for
+pub const TWO_FACTOR_PERIOD: i64 = 30;
+/// How many periods before or after the current one a code is still accepted in, as the clock of
+/// the phone may be slightly off
+pub const TWO_FACTOR_ALLOWED_DRIFT: i64 = 1;
+/// How many seconds a user has to enter their two-factor code after entering their password
+pub const TWO_FACTOR_LOGIN_TIMEOUT: i64 = 5 * 60;
+/// The number of recovery codes a user gets when they turn on two-factor authentication
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+/// How many seconds browsers can remember the CORS policy for, before asking again
+pub const CORS_MAX_AGE: usize = 60 * 60;
 
 /// Components that are always loaded on all pages
 pub const COMPONENTS_ALWAYS_INCLUDED: &[ReactElement] = &[
@@ -0,0 +1,133 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use actix_web::rt::{
+    self,
+    signal::unix::{signal, SignalKind},
+    time::interval,
+};
+use openssl::ssl::{SniError, SslAcceptor, SslAcceptorBuilder, SslContext, SslFiletype, SslMethod};
+
+use crate::config::TlsConfig;
+
+/// The TLS certificate the server uses for new connections.
+/// It can be replaced while the server runs, so that rotating the certificate does not need a
+/// restart
+pub struct CertificateStore {
+    key_path: PathBuf,
+    cert_path: PathBuf,
+    context: RwLock<SslContext>,
+}
+
+/// Set up TLS with a key and a certificate chain
+fn build_acceptor(key_path: &Path, cert_path: &Path) -> Result<SslAcceptorBuilder, String> {
+    let mut ssl_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())
+        .map_err(|err| format!("could not create an SSL builder: {err}"))?;
+    ssl_builder
+        .set_private_key_file(key_path, SslFiletype::PEM)
+        .map_err(|err| format!("{} is not a valid key: {err}", key_path.display()))?;
+    ssl_builder
+        .set_certificate_chain_file(cert_path)
+        .map_err(|err| format!("{} is not a valid certificate: {err}", cert_path.display()))?;
+    // Make sure a half-replaced pair of files is not used
+    ssl_builder
+        .check_private_key()
+        .map_err(|err| format!("the key does not match the certificate: {err}"))?;
+    Ok(ssl_builder)
+}
+
+/// Get when the key and the certificate files were last changed
+fn modification_times(store: &CertificateStore) -> Option<(SystemTime, SystemTime)> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|metadata| metadata.modified());
+    Some((
+        modified(&store.key_path).ok()?,
+        modified(&store.cert_path).ok()?,
+    ))
+}
+
+impl CertificateStore {
+    /// Load the key and the certificate in the configuration
+    pub fn load(config: &TlsConfig) -> Result<CertificateStore, String> {
+        let context = build_acceptor(&config.key_path, &config.cert_path)?
+            .build()
+            .into_context();
+
+        Ok(CertificateStore {
+            key_path: config.key_path.clone(),
+            cert_path: config.cert_path.clone(),
+            context: RwLock::new(context),
+        })
+    }
+
+    /// Create the TLS settings for the server. Every new connection uses the certificate that is
+    /// current when it is opened
+    pub fn ssl_builder(self: &Arc<Self>) -> Result<SslAcceptorBuilder, String> {
+        let mut ssl_builder = build_acceptor(&self.key_path, &self.cert_path)?;
+
+        let store = self.clone();
+        ssl_builder.set_servername_callback(move |ssl, _alert| {
+            // The callback runs for every handshake, whether or not the client sent a server name
+            let context = store.context.read().unwrap_or_else(|err| err.into_inner());
+            ssl.set_ssl_context(&context).map_err(|err| {
+                log::error!("tls.set_context: {}", err);
+                SniError::ALERT_FATAL
+            })
+        });
+
+        Ok(ssl_builder)
+    }
+
+    /// Read the key and the certificate again. If they can not be used, the old ones are kept
+    pub fn reload(&self) {
+        match build_acceptor(&self.key_path, &self.cert_path) {
+            Ok(ssl_builder) => {
+                let context = ssl_builder.build().into_context();
+                *self.context.write().unwrap_or_else(|err| err.into_inner()) = context;
+                log::info!("tls.reload: loaded the new certificate");
+            }
+            Err(err) => {
+                log::error!("tls.reload: {}. Keeping the old certificate", err);
+            }
+        }
+    }
+
+    /// Reload the certificate when the server receives SIGHUP, and when the files change if
+    /// `check_interval` is set
+    pub fn watch(self: &Arc<Self>, check_interval: Option<Duration>) {
+        let store = self.clone();
+        rt::spawn(async move {
+            let mut hangups = match signal(SignalKind::hangup()) {
+                Ok(hangups) => hangups,
+                Err(err) => {
+                    log::error!("tls.watch.signal: {}", err);
+                    return;
+                }
+            };
+            while hangups.recv().await.is_some() {
+                log::info!("tls.watch.signal: received SIGHUP");
+                store.reload();
+            }
+        });
+
+        if let Some(check_interval) = check_interval {
+            let store = self.clone();
+            rt::spawn(async move {
+                let mut last_modified = modification_times(&store);
+                let mut ticks = interval(check_interval);
+                loop {
+                    ticks.tick().await;
+                    let modified = modification_times(&store);
+                    // The files may be missing for a moment while they are replaced
+                    if modified.is_some() && modified != last_modified {
+                        last_modified = modified;
+                        store.reload();
+                    }
+                }
+            });
+        }
+    }
+}